@@ -0,0 +1,311 @@
+use crate::{
+    error::Error,
+    logging,
+    metrics::{self, RequestLabels},
+    settings::{AccessLogConfig, AccessLogFormat},
+};
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description,
+    OffsetDateTime,
+};
+
+#[derive(Debug)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    file: Option<Mutex<File>>,
+    colour: bool,
+}
+
+impl AccessLog {
+    pub fn new(config: &AccessLogConfig) -> Result<AccessLog, Error> {
+        let file = config
+            .path
+            .as_ref()
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .transpose()?
+            .map(Mutex::new);
+
+        // Colours only make sense on a terminal, not in a file or a pipe.
+        let colour = file.is_none() && logging::use_colour(&std::io::stdout());
+
+        Ok(AccessLog {
+            format: config.format,
+            file,
+            colour,
+        })
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let line = match self.format {
+            AccessLogFormat::Pretty => entry.pretty(self.colour),
+            AccessLogFormat::Json => entry.json(),
+            AccessLogFormat::Combined => entry.combined(),
+        };
+
+        match &self.file {
+            Some(file) => {
+                let mut file = file.lock().expect("Access log lock poisoned.");
+
+                if let Err(e) = writeln!(file, "{}", line) {
+                    tracing::error!(error = %e, "Unable to write access log");
+                }
+            }
+            None => println!("{}", line),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccessLogEntry {
+    pub request_id: u64,
+
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub timestamp: OffsetDateTime,
+    pub client_addr: SocketAddr,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub host: Option<String>,
+    pub status: u16,
+    pub duration_ms: f64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    fn target(&self) -> String {
+        match (&self.upstream, &self.redirect, &self.error) {
+            (Some(upstream), _, _) => upstream.clone(),
+            (None, Some(redirect), _) => redirect.clone(),
+            (None, None, Some(error)) => format!("({})", error),
+            (None, None, None) => String::from("-"),
+        }
+    }
+
+    fn pretty(&self, colour: bool) -> String {
+        let path = self.uri.split('?').next().unwrap_or("");
+
+        if !colour {
+            return format!(
+                "{} {} {} -> {} {:.1}ms",
+                self.status,
+                self.method,
+                path,
+                self.target(),
+                self.duration_ms
+            );
+        }
+
+        format!(
+            "{} {} {} \x1b[94m➡\x1b[0m {} \x1b[90m{:.1}ms\x1b[0m",
+            colourise_status(self.status),
+            self.method,
+            path,
+            self.target(),
+            self.duration_ms
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Access log entry should serialise to JSON.")
+    }
+
+    // Apache/NGINX "combined" format, so existing log tooling can read it.
+    fn combined(&self) -> String {
+        let timestamp = self
+            .timestamp
+            .format(format_description!(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+            ))
+            .unwrap_or_default();
+
+        let bytes_out = match self.bytes_out {
+            0 => String::from("-"),
+            bytes => bytes.to_string(),
+        };
+
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\"",
+            self.client_addr.ip(),
+            timestamp,
+            self.method,
+            self.uri,
+            self.version,
+            self.status,
+            bytes_out,
+            escape(self.referer.as_deref().unwrap_or("-")),
+            escape(self.user_agent.as_deref().unwrap_or("-")),
+        )
+    }
+}
+
+pub fn colourise_status(status_code: u16) -> String {
+    match status_code {
+        200..=399 => format!("\x1b[92m{}\x1b[0m", status_code),
+        400..=499 => format!("\x1b[93m{}\x1b[0m", status_code),
+        500..=599 => format!("\x1b[91m{}\x1b[0m", status_code),
+        _ => status_code.to_string(),
+    }
+}
+
+fn serialize_timestamp<S: serde::Serializer>(
+    timestamp: &OffsetDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&timestamp.format(&Rfc3339).unwrap_or_default())
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Counts the bytes of a request body as it's streamed to the upstream.
+pub struct CountedBody<B> {
+    inner: B,
+    bytes: Arc<AtomicU64>,
+}
+
+impl<B> CountedBody<B> {
+    pub fn new(inner: B, bytes: Arc<AtomicU64>) -> CountedBody<B> {
+        CountedBody { inner, bytes }
+    }
+}
+
+impl<B> Body for CountedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// Wraps a response body and writes the access log entry and metrics once the
+// body has been sent (or dropped), so the duration and size cover the whole
+// response.
+pub struct LoggedBody<B> {
+    inner: B,
+    entry: AccessLogEntry,
+    labels: RequestLabels,
+    started: Instant,
+    bytes_in: Arc<AtomicU64>,
+    access_log: Arc<AccessLog>,
+}
+
+impl<B> LoggedBody<B> {
+    pub fn new(
+        inner: B,
+        entry: AccessLogEntry,
+        labels: RequestLabels,
+        started: Instant,
+        bytes_in: Arc<AtomicU64>,
+        access_log: Arc<AccessLog>,
+    ) -> LoggedBody<B> {
+        LoggedBody {
+            inner,
+            entry,
+            labels,
+            started,
+            bytes_in,
+            access_log,
+        }
+    }
+}
+
+impl<B> Body for LoggedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.entry.bytes_out += data.len() as u64;
+            }
+        }
+
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for LoggedBody<B> {
+    fn drop(&mut self) {
+        let duration = self.started.elapsed();
+
+        self.entry.duration_ms = duration.as_secs_f64() * 1000.0;
+        self.entry.bytes_in = self.bytes_in.load(Ordering::Relaxed);
+
+        self.access_log.write(&self.entry);
+
+        metrics::record_request(std::mem::take(&mut self.labels), duration);
+    }
+}
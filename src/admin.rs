@@ -0,0 +1,374 @@
+use crate::{
+    balancer::Balancer,
+    error::Error,
+    health::{Health, UpstreamHealth},
+    metrics,
+    response::{full, ErrorPage, ProxyBody},
+    settings::{ProxyConfig, RouteEdit, Settings},
+    shutdown::ShutdownHandle,
+};
+use http_body_util::BodyExt;
+use hyper::{
+    body::Incoming,
+    header::{self, HeaderValue},
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::{convert::Infallible, str::FromStr, sync::Arc};
+use tokio::{net::TcpListener, sync::watch};
+use tracing::Instrument;
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+type Listeners = Arc<Vec<watch::Sender<Arc<Settings>>>>;
+
+#[derive(Debug, serde::Serialize)]
+struct ListenerRoutes {
+    listener: String,
+    routes: Vec<Route>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Route {
+    route: String,
+    local_host: Option<String>,
+    local_path: String,
+    upstream: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Upstream {
+    upstream: String,
+    routes: Vec<String>,
+    active_requests: usize,
+
+    #[serde(flatten)]
+    health: UpstreamHealth,
+}
+
+pub async fn serve(
+    listener: Arc<TcpListener>,
+    listeners: Vec<watch::Sender<Arc<Settings>>>,
+    health: Health,
+    balancer: Balancer,
+    shutdown: ShutdownHandle,
+) -> Result<(), Error> {
+    let local_addr = listener.local_addr()?;
+    let listeners = Arc::new(listeners);
+
+    tracing::info!(%local_addr, "Admin listening");
+
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.requested() => break,
+        };
+
+        let span = tracing::info_span!("admin", %remote_addr);
+
+        shutdown.spawn(
+            serve_connection(
+                TokioIo::new(stream),
+                listeners.clone(),
+                health.clone(),
+                balancer.clone(),
+                shutdown.clone(),
+            )
+            .instrument(span),
+        );
+    }
+
+    Ok(())
+}
+
+async fn serve_connection(
+    io: TokioIo<tokio::net::TcpStream>,
+    listeners: Listeners,
+    health: Health,
+    balancer: Balancer,
+    shutdown: ShutdownHandle,
+) {
+    let builder =
+        hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
+
+    let connection = builder.serve_connection(
+        io,
+        service_fn(move |req| {
+            handle(req, listeners.clone(), health.clone(), balancer.clone())
+        }),
+    );
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = shutdown.requested() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "Error serving admin connection");
+    }
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    listeners: Listeners,
+    health: Health,
+    balancer: Balancer,
+) -> Result<Response<ProxyBody>, Infallible> {
+    let method = req.method().clone();
+    let path = String::from(req.uri().path());
+    let host = query_param(req.uri().query(), "host");
+
+    let res = match (&method, path.as_str()) {
+        (&Method::GET, "/metrics") => {
+            let mut res = Response::new(full(metrics::render()));
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(METRICS_CONTENT_TYPE),
+            );
+            res
+        }
+        (&Method::GET, "/routes") => json(StatusCode::OK, &routes(&listeners)),
+        (&Method::GET, "/upstreams") => {
+            json(StatusCode::OK, &upstreams(&listeners, &health, &balancer))
+        }
+        (&Method::POST, "/routes") => match read_proxy(req).await {
+            Ok(proxy) => add_route(&listeners, proxy),
+            Err(res) => res,
+        },
+        (&Method::PUT, "/routes") => match read_proxy(req).await {
+            Ok(proxy) => update_route(&listeners, proxy),
+            Err(res) => res,
+        },
+        (&Method::DELETE, path) if path.starts_with("/routes/") => {
+            let local_path = &path["/routes".len()..];
+
+            remove_route(&listeners, host.as_deref(), local_path)
+        }
+        _ => error(
+            StatusCode::NOT_FOUND,
+            &format!("No admin endpoint {}", path),
+        ),
+    };
+
+    Ok(res)
+}
+
+fn routes(listeners: &Listeners) -> Vec<ListenerRoutes> {
+    listeners
+        .iter()
+        .map(|listener| {
+            let settings = listener.borrow();
+
+            ListenerRoutes {
+                listener: format!("{}:{}", settings.host, settings.local_port),
+                routes: settings
+                    .proxies
+                    .iter()
+                    .map(|proxy| Route {
+                        route: proxy.to_string(),
+                        local_host: proxy.local_host.clone(),
+                        local_path: proxy.local_path.clone(),
+                        upstream: proxy.upstream(&settings.host),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn upstreams(
+    listeners: &Listeners,
+    health: &Health,
+    balancer: &Balancer,
+) -> Vec<Upstream> {
+    let mut upstreams: Vec<Upstream> = vec![];
+
+    for listener in listeners.iter() {
+        let settings = listener.borrow();
+
+        let targets = settings
+            .proxies
+            .iter()
+            .chain(settings.fallback.iter())
+            .flat_map(|proxy| {
+                let route = proxy.to_string();
+
+                proxy
+                    .upstream_targets()
+                    .into_iter()
+                    .map(move |target| (route.clone(), target))
+            });
+
+        for (route, target) in targets {
+            let upstream = target.upstream(&settings.host);
+
+            match upstreams.iter_mut().find(|u| u.upstream == upstream) {
+                Some(existing) => {
+                    if !existing.routes.contains(&route) {
+                        existing.routes.push(route);
+                    }
+                }
+                None => upstreams.push(Upstream {
+                    active_requests: balancer.active_requests(&upstream),
+                    health: health.get(&upstream),
+                    upstream,
+                    routes: vec![route],
+                }),
+            }
+        }
+    }
+
+    upstreams
+}
+
+fn add_route(listeners: &Listeners, proxy: ProxyConfig) -> Response<ProxyBody> {
+    if listeners
+        .iter()
+        .any(|l| position(&l.borrow(), &proxy).is_some())
+    {
+        return error(
+            StatusCode::CONFLICT,
+            &format!("A route for {} already exists", route_key(&proxy)),
+        );
+    }
+
+    for listener in listeners.iter() {
+        listener.send_modify(|settings| {
+            Arc::make_mut(settings).edit_route(RouteEdit::Set(proxy.clone()));
+        });
+    }
+
+    tracing::info!(route = %proxy, "Added route");
+
+    json(StatusCode::CREATED, &proxy.to_string())
+}
+
+fn update_route(
+    listeners: &Listeners,
+    proxy: ProxyConfig,
+) -> Response<ProxyBody> {
+    let mut updated = false;
+
+    for listener in listeners.iter() {
+        updated |= listener.send_if_modified(|settings| {
+            position(settings, &proxy).is_some()
+                && Arc::make_mut(settings)
+                    .edit_route(RouteEdit::Set(proxy.clone()))
+        });
+    }
+
+    if !updated {
+        return error(
+            StatusCode::NOT_FOUND,
+            &format!("No route for {}", route_key(&proxy)),
+        );
+    }
+
+    tracing::info!(route = %proxy, "Updated route");
+
+    json(StatusCode::OK, &proxy.to_string())
+}
+
+fn remove_route(
+    listeners: &Listeners,
+    local_host: Option<&str>,
+    local_path: &str,
+) -> Response<ProxyBody> {
+    let key = ProxyConfig {
+        local_host: local_host.map(|h| h.to_ascii_lowercase()),
+        local_path: String::from(local_path),
+        ..ProxyConfig::default()
+    };
+
+    let mut removed = false;
+
+    for listener in listeners.iter() {
+        removed |= listener.send_if_modified(|settings| {
+            position(settings, &key).is_some()
+                && Arc::make_mut(settings)
+                    .edit_route(RouteEdit::Remove(key.clone()))
+        });
+    }
+
+    if !removed {
+        return error(
+            StatusCode::NOT_FOUND,
+            &format!("No route for {}", route_key(&key)),
+        );
+    }
+
+    tracing::info!(route = %route_key(&key), "Removed route");
+
+    let mut res = Response::new(full(""));
+    *res.status_mut() = StatusCode::NO_CONTENT;
+    res
+}
+
+fn position(settings: &Settings, proxy: &ProxyConfig) -> Option<usize> {
+    settings.proxies.iter().position(|p| p.same_route(proxy))
+}
+
+fn route_key(proxy: &ProxyConfig) -> String {
+    format!(
+        "{}{}",
+        proxy.local_host.as_deref().unwrap_or(""),
+        proxy.local_path
+    )
+}
+
+// The body is a proxy definition in the same form as `--proxy`, e.g.
+// `api:3001/api`.
+async fn read_proxy(
+    req: Request<Incoming>,
+) -> Result<ProxyConfig, Response<ProxyBody>> {
+    let body = req
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?
+        .to_bytes();
+
+    let definition = std::str::from_utf8(&body)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+
+    ProxyConfig::from_str(definition.trim()).map_err(|e| {
+        error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid proxy definition '{}': {}", definition.trim(), e),
+        )
+    })
+}
+
+fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| String::from(value))
+}
+
+fn json(
+    status: StatusCode,
+    value: &impl serde::Serialize,
+) -> Response<ProxyBody> {
+    let body = serde_json::to_string(value)
+        .expect("Admin response should serialise to JSON.");
+
+    let mut res = Response::new(full(body));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res
+}
+
+fn error(status: StatusCode, message: &str) -> Response<ProxyBody> {
+    ErrorPage::new(status, message)
+        .into_response(Some(&HeaderValue::from_static("application/json")))
+}
@@ -0,0 +1,81 @@
+use crate::{
+    balancer,
+    settings::{AffinityConfig, ProxyConfig},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hyper::{header::HeaderValue, HeaderMap};
+use lazy_static::lazy_static;
+use ring::hmac;
+
+lazy_static! {
+    static ref PROCESS_KEY: [u8; 32] = rand::random();
+}
+
+fn key(config: &AffinityConfig) -> hmac::Key {
+    match &config.secret {
+        Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        None => hmac::Key::new(hmac::HMAC_SHA256, &*PROCESS_KEY),
+    }
+}
+
+// The signature covers the route as well as the upstream, so a cookie can't
+// be used to reach an upstream through a route that doesn't proxy to it.
+fn message(proxy: &ProxyConfig, upstream: &str) -> String {
+    format!(
+        "{}{}|{}",
+        proxy.local_host.as_deref().unwrap_or(""),
+        proxy.local_path,
+        upstream
+    )
+}
+
+pub fn sign(
+    config: &AffinityConfig,
+    proxy: &ProxyConfig,
+    upstream: &str,
+) -> String {
+    let tag = hmac::sign(&key(config), message(proxy, upstream).as_bytes());
+
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(upstream),
+        URL_SAFE_NO_PAD.encode(tag.as_ref())
+    )
+}
+
+pub fn verify(
+    config: &AffinityConfig,
+    proxy: &ProxyConfig,
+    value: &str,
+) -> Option<String> {
+    let (upstream, tag) = value.split_once('.')?;
+
+    let upstream =
+        String::from_utf8(URL_SAFE_NO_PAD.decode(upstream).ok()?).ok()?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+
+    hmac::verify(&key(config), message(proxy, &upstream).as_bytes(), &tag)
+        .ok()?;
+
+    Some(upstream)
+}
+
+// The upstream that the client is pinned to, if it sent a valid cookie.
+pub fn pinned(proxy: &ProxyConfig, headers: &HeaderMap) -> Option<String> {
+    let config = proxy.affinity.as_ref()?;
+    let value = balancer::cookie(headers, &config.cookie)?;
+
+    verify(config, proxy, &value)
+}
+
+pub fn set_cookie(proxy: &ProxyConfig, upstream: &str) -> Option<HeaderValue> {
+    let config = proxy.affinity.as_ref()?;
+
+    HeaderValue::from_str(&format!(
+        "{}={}; Path={}; HttpOnly; SameSite=Lax",
+        config.cookie,
+        sign(config, proxy, upstream),
+        proxy.local_path
+    ))
+    .ok()
+}
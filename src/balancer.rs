@@ -0,0 +1,195 @@
+use crate::settings::{HashKey, LoadBalancing, ProxyConfig};
+use hyper::{
+    body::{Body, Bytes, Frame, SizeHint},
+    HeaderMap, Request,
+};
+use rand::Rng;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+// Round robin counters and requests in flight, kept per server so that
+// servers in the same process don't skew each other's balancing.
+#[derive(Debug, Default, Clone)]
+pub struct Balancer {
+    next: Arc<Mutex<HashMap<String, usize>>>,
+    active: Arc<Mutex<HashMap<String, Arc<AtomicUsize>>>>,
+}
+
+impl Balancer {
+    pub fn new() -> Balancer {
+        Balancer::default()
+    }
+
+    // Orders the available targets of a proxy so that the first is the one to
+    // use, and the rest are what to fail over to.
+    pub fn order<B>(
+        &self,
+        proxy: &ProxyConfig,
+        mut targets: Vec<ProxyConfig>,
+        host: &str,
+        req: &Request<B>,
+        client_addr: SocketAddr,
+    ) -> Vec<ProxyConfig> {
+        if targets.len() < 2 {
+            return targets;
+        }
+
+        match &proxy.load_balancing {
+            LoadBalancing::Failover => {}
+            LoadBalancing::RoundRobin => {
+                let start = self.next(proxy);
+                let len = targets.len();
+
+                targets.rotate_left(start % len);
+            }
+            LoadBalancing::LeastConnections => {
+                targets
+                    .sort_by_key(|t| self.active_requests(&t.upstream(host)));
+            }
+            LoadBalancing::Random => {
+                let start = rand::thread_rng().gen_range(0..targets.len());
+
+                targets.rotate_left(start);
+            }
+            LoadBalancing::Hash(key) => match hash_key(key, req, client_addr) {
+                // Rendezvous hashing, so that a target going down only moves
+                // the keys that were on it.
+                Some(key) => targets.sort_by_key(|t| {
+                    let mut hasher = DefaultHasher::new();
+                    (&key, t.upstream(host)).hash(&mut hasher);
+
+                    std::cmp::Reverse(hasher.finish())
+                }),
+                None => {
+                    let start = self.next(proxy);
+                    let len = targets.len();
+
+                    targets.rotate_left(start % len);
+                }
+            },
+        }
+
+        targets
+    }
+
+    fn next(&self, proxy: &ProxyConfig) -> usize {
+        let mut next = self.next.lock().expect("Load balancer lock poisoned.");
+        let counter = next.entry(proxy.to_string()).or_default();
+
+        let current = *counter;
+        *counter = counter.wrapping_add(1);
+
+        current
+    }
+
+    pub fn active_requests(&self, upstream: &str) -> usize {
+        self.active
+            .lock()
+            .expect("Load balancer lock poisoned.")
+            .get(upstream)
+            .map(|active| active.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    pub fn request_started(&self, upstream: &str) -> ActiveRequest {
+        let active = self
+            .active
+            .lock()
+            .expect("Load balancer lock poisoned.")
+            .entry(String::from(upstream))
+            .or_default()
+            .clone();
+
+        active.fetch_add(1, Ordering::Relaxed);
+
+        ActiveRequest(active)
+    }
+}
+
+pub fn prefer(targets: &mut [ProxyConfig], upstream: &str, host: &str) {
+    if let Some(i) = targets.iter().position(|t| t.upstream(host) == upstream) {
+        targets[..=i].rotate_right(1);
+    }
+}
+
+fn hash_key<B>(
+    key: &HashKey,
+    req: &Request<B>,
+    client_addr: SocketAddr,
+) -> Option<String> {
+    match key {
+        HashKey::Header(name) => req
+            .headers()
+            .get(name.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        HashKey::Cookie(name) => cookie(req.headers(), name),
+        HashKey::ClientIp => Some(client_addr.ip().to_string()),
+    }
+}
+
+pub fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(hyper::header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| String::from(value))
+}
+
+pub struct ActiveRequest(Arc<AtomicUsize>);
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Keeps a request counted as active until its response body is done.
+pub struct TrackedBody<B> {
+    inner: B,
+    _active: ActiveRequest,
+}
+
+impl<B> TrackedBody<B> {
+    pub fn new(inner: B, active: ActiveRequest) -> TrackedBody<B> {
+        TrackedBody {
+            inner,
+            _active: active,
+        }
+    }
+}
+
+impl<B> Body for TrackedBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -1,37 +1,93 @@
-use std::sync::Arc;
+use std::{ffi::OsString, time::Duration};
 
+use clap::Parser;
 use joubini::{
-    error::{Error, IoError},
-    server::start,
+    ca::LocalCa,
+    cli::{CaCommand, Cli, Command},
+    error::Error,
+    logging, reload,
+    server::ProxyServer,
     settings::get_settings,
+    shutdown::ShutdownHandle,
 };
-use tokio::net::TcpListener;
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    match get_settings(std::env::args_os().collect()) {
-        Ok(settings) => {
-            let settings = Arc::new(settings);
-
-            match TcpListener::bind(format!(
-                "{}:{}",
-                settings.host, settings.local_port
-            ))
-            .await
-            {
-                Ok(listener) => {
-                    let listener = Arc::new(listener);
-                    start(listener.clone(), settings.clone()).await
-                }
-                Err(e) => {
-                    eprintln!("Unable to bind to local port: {}", e);
-                    Err(Error::IoError(IoError::StdIo(e)))
-                }
-            }
-        }
-        Err(e) => {
-            eprintln!("Unable to get settings: {}", e);
-            Err(e)
-        }
+    let cli_args: Vec<OsString> = std::env::args_os().collect();
+
+    let cli = Cli::parse_from(&cli_args);
+
+    if let Some(Command::Ca {
+        command: CaCommand::Export,
+    }) = cli.command
+    {
+        return export_ca(cli_args);
+    }
+
+    logging::init(cli.log_level.as_deref())?;
+
+    let settings = get_settings(cli_args.clone()).map_err(|e| {
+        tracing::error!(error = %e, "Unable to get settings");
+        e
+    })?;
+
+    let server = ProxyServer::builder()
+        .settings(settings)
+        .bind()
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Unable to start server");
+            e
+        })?;
+
+    tokio::spawn(reload::watch(
+        cli_args,
+        server.settings_senders(),
+        RELOAD_INTERVAL,
+    ));
+    tokio::spawn(shutdown_on_signal(server.shutdown_handle()));
+
+    server.join().await
+}
+
+async fn shutdown_on_signal(shutdown: ShutdownHandle) {
+    wait_for_signal().await;
+
+    tracing::info!("Shutting down, press Ctrl-C again to exit immediately");
+    shutdown.shutdown();
+
+    wait_for_signal().await;
+
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM.");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
     }
 }
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Unable to listen for Ctrl-C.");
+}
+
+fn export_ca(cli_args: Vec<OsString>) -> Result<(), Error> {
+    let settings = get_settings(cli_args)?;
+    let ca = LocalCa::load_or_create(settings.ca_dir.as_deref())?;
+
+    print!("{}", ca.root_pem());
+
+    Ok(())
+}
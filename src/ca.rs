@@ -0,0 +1,260 @@
+use crate::error::{Error, TlsError};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::{
+    pki_types::CertificateDer,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use time::OffsetDateTime;
+
+const CA_CERT_FILE: &str = "ca.crt";
+const CA_KEY_FILE: &str = "ca.key";
+const CERTS_DIR: &str = "certs";
+const DEFAULT_HOST: &str = "localhost";
+
+// Browsers reject leaf certificates that are valid for more than 398 days, so
+// cached certificates are reissued well before they expire.
+const LEAF_VALIDITY_DAYS: i64 = 397;
+const LEAF_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+const CA_VALIDITY_DAYS: i64 = 10 * 365;
+
+pub fn default_ca_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("joubini")
+}
+
+pub struct LocalCa {
+    dir: PathBuf,
+    cert: Certificate,
+    cert_der: CertificateDer<'static>,
+    cert_pem: String,
+    leaves: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl Debug for LocalCa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalCa").field("dir", &self.dir).finish()
+    }
+}
+
+impl LocalCa {
+    pub fn load_or_create(dir: Option<&Path>) -> Result<LocalCa, Error> {
+        let dir = dir.map(PathBuf::from).unwrap_or_else(default_ca_dir);
+
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+
+        let (cert_pem, key_pem) = if cert_path.exists() && key_path.exists() {
+            (
+                fs::read_to_string(&cert_path)?,
+                fs::read_to_string(&key_path)?,
+            )
+        } else {
+            let cert = Certificate::from_params(ca_params())?;
+            let cert_pem = cert.serialize_pem()?;
+            let key_pem = cert.serialize_private_key_pem();
+
+            fs::create_dir_all(&dir)?;
+            fs::write(&cert_path, &cert_pem)?;
+            write_private(&key_path, &key_pem)?;
+
+            (cert_pem, key_pem)
+        };
+
+        let key_pair = KeyPair::from_pem(&key_pem)?;
+        let cert = Certificate::from_params(
+            CertificateParams::from_ca_cert_pem(&cert_pem, key_pair)?,
+        )?;
+
+        let cert_der = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .next()
+            .ok_or(Error::TlsError(TlsError::InvalidCertificate(
+                cert_path.clone(),
+            )))??;
+
+        Ok(LocalCa {
+            dir,
+            cert,
+            cert_der,
+            cert_pem,
+            leaves: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn root_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    pub fn certified_key(
+        &self,
+        host: &str,
+    ) -> Result<Arc<CertifiedKey>, Error> {
+        let host = host.to_ascii_lowercase();
+
+        if let Some(key) = self
+            .leaves
+            .lock()
+            .expect("Certificate cache lock poisoned.")
+            .get(&host)
+        {
+            return Ok(key.clone());
+        }
+
+        // Issued without holding the lock, so that handshakes for other hosts
+        // aren't held up by key generation and file IO. If two handshakes race
+        // to issue for the same host, the first to finish is kept.
+        let key = Arc::new(self.load_or_issue(&host)?);
+
+        Ok(self
+            .leaves
+            .lock()
+            .expect("Certificate cache lock poisoned.")
+            .entry(host)
+            .or_insert(key)
+            .clone())
+    }
+
+    fn load_or_issue(&self, host: &str) -> Result<CertifiedKey, Error> {
+        // SNI names are DNS names, but they still end up in a file path.
+        if host.is_empty()
+            || host.starts_with('.')
+            || host.contains(['/', '\\'])
+        {
+            return Err(Error::TlsError(TlsError::InvalidHostname(
+                String::from(host),
+            )));
+        }
+
+        let cert_path = self.dir.join(CERTS_DIR).join(format!("{}.crt", host));
+        let key_path = self.dir.join(CERTS_DIR).join(format!("{}.key", host));
+
+        let (cert_pem, key_pem) = if is_fresh(&cert_path) && key_path.exists() {
+            (
+                fs::read_to_string(&cert_path)?,
+                fs::read_to_string(&key_path)?,
+            )
+        } else {
+            let (cert_pem, key_pem) = self.issue(host)?;
+
+            fs::create_dir_all(self.dir.join(CERTS_DIR))?;
+            fs::write(&cert_path, &cert_pem)?;
+            write_private(&key_path, &key_pem)?;
+
+            (cert_pem, key_pem)
+        };
+
+        let cert = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .next()
+            .ok_or(Error::TlsError(TlsError::InvalidCertificate(
+                cert_path.clone(),
+            )))??;
+
+        let key = rustls_pemfile::private_key(&mut key_pem.as_bytes())?
+            .ok_or(Error::TlsError(TlsError::InvalidPrivateKey(key_path)))?;
+
+        Ok(CertifiedKey::new(
+            vec![cert, self.cert_der.clone()],
+            rustls::crypto::ring::sign::any_supported_type(&key)?,
+        ))
+    }
+
+    fn issue(&self, host: &str) -> Result<(String, String), Error> {
+        let now = OffsetDateTime::now_utc();
+
+        let mut params = CertificateParams::new(vec![String::from(host)]);
+        params.distinguished_name.push(DnType::CommonName, host);
+        params.not_before = now - time::Duration::days(1);
+        params.not_after = now + time::Duration::days(LEAF_VALIDITY_DAYS);
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+
+        let cert = Certificate::from_params(params)?;
+
+        Ok((
+            cert.serialize_pem_with_signer(&self.cert)?,
+            cert.serialize_private_key_pem(),
+        ))
+    }
+}
+
+impl ResolvesServerCert for LocalCa {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let host = client_hello.server_name().unwrap_or(DEFAULT_HOST);
+
+        match self.certified_key(host) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                tracing::error!(
+                    server_name = host,
+                    error = %e,
+                    "Unable to issue certificate"
+                );
+
+                None
+            }
+        }
+    }
+}
+
+fn ca_params() -> CertificateParams {
+    let now = OffsetDateTime::now_utc();
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name.remove(DnType::CommonName);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "joubini development CA");
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "joubini");
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.not_before = now - time::Duration::days(1);
+    params.not_after = now + time::Duration::days(CA_VALIDITY_DAYS);
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+
+    params
+}
+
+fn is_fresh(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < LEAF_MAX_AGE)
+}
+
+fn write_private(path: &Path, contents: &str) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::{
+    error::{Error, TlsError},
+    settings::CertificateConfig,
+};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use std::{
+    collections::HashMap, fs::File, io::BufReader, path::Path, sync::Arc,
+};
+
+#[derive(Debug)]
+pub struct CertStore {
+    hosts: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl CertStore {
+    pub fn load(
+        certificates: &[CertificateConfig],
+        default: Option<(&Path, &Path)>,
+    ) -> Result<CertStore, Error> {
+        let mut hosts = HashMap::new();
+
+        for certificate in certificates {
+            let key = Arc::new(load_certified_key(
+                &certificate.pem,
+                &certificate.key,
+            )?);
+
+            for host in &certificate.hosts {
+                hosts.insert(host.to_ascii_lowercase(), key.clone());
+            }
+        }
+
+        let default = default
+            .map(|(pem, key)| load_certified_key(pem, key).map(Arc::new))
+            .transpose()?;
+
+        Ok(CertStore { hosts, default })
+    }
+
+    pub fn find(&self, host: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let Some(host) = host.map(|h| h.to_ascii_lowercase()) else {
+            return self.default.clone();
+        };
+
+        // A wildcard only covers a single label, as in the certificate itself.
+        let wildcard = host
+            .split_once('.')
+            .map(|(_, parent)| format!("*.{}", parent));
+
+        self.hosts
+            .get(&host)
+            .or_else(|| wildcard.and_then(|w| self.hosts.get(&w)))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let host = client_hello.server_name();
+        let key = self.find(host);
+
+        if key.is_none() {
+            tracing::warn!(
+                server_name = host.unwrap_or("(none)"),
+                "No certificate configured"
+            );
+        }
+
+        key
+    }
+}
+
+pub fn load_certified_key(
+    pem: &Path,
+    key: &Path,
+) -> Result<CertifiedKey, Error> {
+    let certs = rustls_pemfile::certs(&mut open(pem)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::TlsError(TlsError::ReadFile(pem.into(), e)))?;
+
+    if certs.is_empty() {
+        return Err(Error::TlsError(TlsError::InvalidCertificate(pem.into())));
+    }
+
+    // Accepts PKCS#1 (`RSA PRIVATE KEY`), PKCS#8 (`PRIVATE KEY`) and SEC1
+    // (`EC PRIVATE KEY`) keys.
+    let private_key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|e| Error::TlsError(TlsError::ReadFile(key.into(), e)))?
+        .ok_or(Error::TlsError(TlsError::InvalidPrivateKey(key.into())))?;
+
+    let signing_key =
+        rustls::crypto::ring::sign::any_supported_type(&private_key).map_err(
+            |e| Error::TlsError(TlsError::UnsupportedPrivateKey(key.into(), e)),
+        )?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, Error> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| Error::TlsError(TlsError::ReadFile(path.into(), e)))
+}
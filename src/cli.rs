@@ -29,7 +29,7 @@ pub struct Cli {
         short = 'p',
         long = "proxy",
         name = "proxy_config",
-        help = "Configuration for proxy in format '<:local_port?></local_path?><:remote_port!></remote_path?>'"
+        help = "Configuration for proxy in format '<//local_host?></local_path?><:remote_host?><:remote_port!></remote_path?>' or '<//local_host?></local_path?><:http://remote_host:remote_port/remote_path>'"
     )]
     pub proxies: Vec<String>,
 
@@ -51,13 +51,77 @@ pub struct Cli {
 
     #[clap(
         long = "pem",
-        help = "Path to SSL certificate as `.pem` or `.crt`. Required if `--tls` flag is enabled."
+        help = "Path to SSL certificate as `.pem` or `.crt`. If omitted with `--tls`, certificates are issued by a local development CA."
     )]
     pub pem: Option<PathBuf>,
 
     #[clap(
         long = "key",
-        help = "Path to TLS/SSL certificate key as `.key`. Required if `--tls` flag is enabled."
+        help = "Path to TLS/SSL certificate key as `.key`. Required if `--pem` is provided."
     )]
     pub key: Option<PathBuf>,
+
+    #[clap(
+        long = "ca-dir",
+        help = "Directory to store the local development CA and the certificates it issues"
+    )]
+    pub ca_dir: Option<PathBuf>,
+
+    #[clap(
+        long = "access-log",
+        help = "Path to write the access log to instead of stdout"
+    )]
+    pub access_log: Option<PathBuf>,
+
+    #[clap(
+        long = "access-log-format",
+        help = "Access log format: pretty, json or combined [default: pretty]"
+    )]
+    pub access_log_format: Option<String>,
+
+    #[clap(
+        long = "admin-port",
+        help = "Port for the admin listener, which serves metrics and the admin API"
+    )]
+    pub admin_port: Option<u16>,
+
+    #[clap(
+        long = "log-level",
+        help = "Log level or filter, e.g. `debug` or `joubini=debug,hyper=info`. Overrides `RUST_LOG` [default: info]"
+    )]
+    pub log_level: Option<String>,
+
+    #[clap(
+        long = "fallback",
+        name = "fallback_proxy_config",
+        help = "Proxy configuration used for requests that don't match any other proxy, in the same format as `--proxy` but without a local path"
+    )]
+    pub fallback: Option<String>,
+
+    #[clap(
+        long = "dev",
+        default_value = "false",
+        help = "Development mode: list the configured proxies when a request doesn't match any of them"
+    )]
+    pub dev: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    #[clap(about = "Manage the local development CA")]
+    Ca {
+        #[clap(subcommand)]
+        command: CaCommand,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum CaCommand {
+    #[clap(
+        about = "Print the root certificate of the local development CA, e.g. to add it to a trust store"
+    )]
+    Export,
 }
@@ -0,0 +1,182 @@
+use crate::{
+    error::{Error, TlsError},
+    response::ProxyBody,
+    settings::{ProxyConfig, Settings, UpstreamProtocol, UpstreamTls},
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::{TokioExecutor, TokioTimer},
+};
+use rustls::{
+    client::danger::{
+        HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub type HttpClient = Client<HttpsConnector<HttpConnector>, ProxyBody>;
+
+#[derive(Clone)]
+pub struct Clients {
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: u64,
+    connect_timeout: u64,
+    clients: Arc<Mutex<HashMap<(UpstreamProtocol, UpstreamTls), HttpClient>>>,
+}
+
+impl Clients {
+    pub fn new(settings: &Settings) -> Clients {
+        Clients {
+            pool_max_idle_per_host: settings.pool_max_idle_per_host,
+            pool_idle_timeout: settings.pool_idle_timeout,
+            connect_timeout: settings.connect_timeout,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn get(&self, proxy: &ProxyConfig) -> Result<HttpClient, Error> {
+        self.get_with_protocol(proxy, proxy.protocol)
+    }
+
+    pub fn get_with_protocol(
+        &self,
+        proxy: &ProxyConfig,
+        protocol: UpstreamProtocol,
+    ) -> Result<HttpClient, Error> {
+        let mut clients =
+            self.clients.lock().expect("Client pool lock poisoned.");
+
+        let key = (protocol, proxy.tls.clone());
+
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = self.build_client(protocol, &proxy.tls)?;
+        clients.insert(key, client.clone());
+
+        Ok(client)
+    }
+
+    fn build_client(
+        &self,
+        protocol: UpstreamProtocol,
+        tls: &UpstreamTls,
+    ) -> Result<HttpClient, Error> {
+        let mut http_connector = HttpConnector::new();
+        http_connector.set_nodelay(true);
+        http_connector.enforce_http(false);
+        http_connector.set_connect_timeout(Some(Duration::from_secs(
+            self.connect_timeout,
+        )));
+
+        let builder = HttpsConnectorBuilder::new()
+            .with_tls_config(build_tls_config(tls)?)
+            .https_or_http();
+
+        let builder = match &tls.sni {
+            Some(sni) => builder.with_server_name(sni.clone()),
+            None => builder,
+        };
+
+        let connector = match protocol {
+            UpstreamProtocol::Http1 => {
+                builder.enable_http1().wrap_connector(http_connector)
+            }
+            UpstreamProtocol::H2c => {
+                builder.enable_http2().wrap_connector(http_connector)
+            }
+            UpstreamProtocol::H2 => {
+                builder.enable_all_versions().wrap_connector(http_connector)
+            }
+        };
+
+        Ok(Client::builder(TokioExecutor::new())
+            .pool_timer(TokioTimer::new())
+            .pool_idle_timeout(Duration::from_secs(self.pool_idle_timeout))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .http2_only(protocol == UpstreamProtocol::H2c)
+            .build(connector))
+    }
+}
+
+pub fn build_tls_config(tls: &UpstreamTls) -> Result<ClientConfig, Error> {
+    if tls.insecure {
+        return Ok(ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification))
+            .with_no_client_auth());
+    }
+
+    let mut roots = RootCertStore::empty();
+
+    for cert in rustls_native_certs::load_native_certs()? {
+        // Ignore unparsable system certificates rather than failing to start.
+        let _ = roots.add(cert);
+    }
+
+    if let Some(ca) = &tls.ca {
+        let read_error = |e| Error::TlsError(TlsError::ReadFile(ca.clone(), e));
+
+        let mut reader = BufReader::new(File::open(ca).map_err(read_error)?);
+
+        for cert in rustls_pemfile::certs(&mut reader) {
+            roots.add(cert.map_err(read_error)?).map_err(|_| {
+                Error::TlsError(TlsError::InvalidCertificate(ca.clone()))
+            })?;
+        }
+    }
+
+    Ok(ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+#[derive(Debug)]
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -3,6 +3,10 @@ use std::fmt::Display;
 #[derive(Debug)]
 pub enum ProxyError {
     RequestFailed(hyper::Error),
+    UpstreamRequestFailed(hyper_util::client::legacy::Error),
+    UpstreamTimeout(u64),
+    InvalidUri(hyper::http::Error),
+    PathMismatch(String, String),
 }
 
 impl Display for ProxyError {
@@ -11,6 +15,22 @@ impl Display for ProxyError {
             ProxyError::RequestFailed(ref e) => {
                 write!(f, "Request failed: {}", e)
             }
+            ProxyError::UpstreamRequestFailed(ref e) => {
+                write!(f, "Upstream request failed: {}", e)
+            }
+            ProxyError::UpstreamTimeout(ref secs) => {
+                write!(f, "Upstream did not respond within {}s", secs)
+            }
+            ProxyError::InvalidUri(ref e) => {
+                write!(f, "Invalid URI: {}", e)
+            }
+            ProxyError::PathMismatch(ref path, ref local_path) => {
+                write!(
+                    f,
+                    "Path '{}' does not match proxy path '{}'",
+                    path, local_path
+                )
+            }
         }
     }
 }
@@ -35,6 +55,10 @@ pub enum ParseError {
     ParseInt(std::num::ParseIntError),
     ProxyDefinition,
     FileConfig(serde_yaml::Error),
+    AccessLogFormat(String),
+    LogLevel(String),
+    LoadBalancing(String),
+    FallbackLocalPath(String),
 }
 
 impl Display for ParseError {
@@ -49,6 +73,104 @@ impl Display for ParseError {
             ParseError::FileConfig(ref e) => {
                 write!(f, "Unable to parse config from config file: {}", e)
             }
+            ParseError::AccessLogFormat(ref format) => {
+                write!(
+                    f,
+                    "Unknown access log format '{}', expected pretty, json or combined",
+                    format
+                )
+            }
+            ParseError::LogLevel(ref level) => {
+                write!(f, "Invalid log level or filter '{}'", level)
+            }
+            ParseError::LoadBalancing(ref strategy) => {
+                write!(
+                    f,
+                    "Unknown load balancing strategy '{}', expected failover, round_robin, least_connections, random or hash:<header:name|cookie:name|client_ip>",
+                    strategy
+                )
+            }
+            ParseError::FallbackLocalPath(ref path) => {
+                write!(
+                    f,
+                    "Fallback proxy can't have a local path '{}', as it handles requests to any path",
+                    path
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Rustls(rustls::Error),
+    CertificateGeneration(rcgen::Error),
+    InvalidCertificate(std::path::PathBuf),
+    InvalidPrivateKey(std::path::PathBuf),
+    UnsupportedPrivateKey(std::path::PathBuf, rustls::Error),
+    MissingCertificateOrKey,
+    ReadFile(std::path::PathBuf, std::io::Error),
+    InvalidHostname(String),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Rustls(ref e) => {
+                write!(f, "Rustls error: {}", e)
+            }
+            TlsError::CertificateGeneration(ref e) => {
+                write!(f, "Unable to generate certificate: {}", e)
+            }
+            TlsError::InvalidCertificate(ref path) => {
+                write!(
+                    f,
+                    "No PEM encoded certificate found in '{}'",
+                    path.display()
+                )
+            }
+            TlsError::InvalidPrivateKey(ref path) => {
+                write!(
+                    f,
+                    "No PEM encoded PKCS#1, PKCS#8 or SEC1 private key found in '{}'",
+                    path.display()
+                )
+            }
+            TlsError::UnsupportedPrivateKey(ref path, ref e) => {
+                write!(
+                    f,
+                    "Unsupported private key in '{}': {}",
+                    path.display(),
+                    e
+                )
+            }
+            TlsError::MissingCertificateOrKey => {
+                write!(f, "Both a certificate and a private key are required")
+            }
+            TlsError::ReadFile(ref path, ref e) => {
+                write!(f, "Unable to read '{}': {}", path.display(), e)
+            }
+            TlsError::InvalidHostname(ref host) => {
+                write!(f, "Invalid hostname for certificate: '{}'", host)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReloadError {
+    ListenersChanged,
+}
+
+impl Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::ListenersChanged => {
+                write!(
+                    f,
+                    "Listener addresses can't be changed without a restart"
+                )
+            }
         }
     }
 }
@@ -58,6 +180,8 @@ pub enum Error {
     IoError(IoError),
     ParseError(ParseError),
     ProxyError(ProxyError),
+    TlsError(TlsError),
+    ReloadError(ReloadError),
 }
 
 impl Display for Error {
@@ -74,6 +198,14 @@ impl Display for Error {
             Error::ProxyError(ref e) => {
                 write!(f, "Proxy error: {}", e)
             }
+
+            Error::TlsError(ref e) => {
+                write!(f, "TLS error: {}", e)
+            }
+
+            Error::ReloadError(ref e) => {
+                write!(f, "Reload error: {}", e)
+            }
         }
     }
 }
@@ -102,4 +234,28 @@ impl From<hyper::Error> for Error {
     }
 }
 
+impl From<rustls::Error> for Error {
+    fn from(value: rustls::Error) -> Self {
+        Error::TlsError(TlsError::Rustls(value))
+    }
+}
+
+impl From<rcgen::Error> for Error {
+    fn from(value: rcgen::Error) -> Self {
+        Error::TlsError(TlsError::CertificateGeneration(value))
+    }
+}
+
+impl From<hyper_util::client::legacy::Error> for Error {
+    fn from(value: hyper_util::client::legacy::Error) -> Self {
+        Error::ProxyError(ProxyError::UpstreamRequestFailed(value))
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(value: hyper::http::Error) -> Self {
+        Error::ProxyError(ProxyError::InvalidUri(value))
+    }
+}
+
 impl std::error::Error for Error {}
@@ -0,0 +1,309 @@
+use crate::{
+    client::Clients,
+    error::Error,
+    response::full,
+    settings::{HealthCheckConfig, ProxyConfig, Settings},
+    shutdown::ShutdownHandle,
+};
+use hyper::{header::HeaderValue, Request, Uri};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+const CHECK_TICK: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct UpstreamHealth {
+    pub status: HealthStatus,
+    pub consecutive_failures: u64,
+    pub consecutive_successes: u64,
+    pub last_error: Option<String>,
+
+    #[serde(skip)]
+    pub ejected_at: Option<Instant>,
+}
+
+impl UpstreamHealth {
+    fn mark(&mut self, upstream: &str, status: HealthStatus) {
+        if self.status == status {
+            return;
+        }
+
+        match status {
+            HealthStatus::Unhealthy => {
+                self.ejected_at = Some(Instant::now());
+
+                tracing::warn!(
+                    upstream,
+                    error = self.last_error.as_deref().unwrap_or(""),
+                    "Upstream marked unhealthy"
+                );
+            }
+            _ => {
+                self.ejected_at = None;
+
+                if self.status == HealthStatus::Unhealthy {
+                    tracing::info!(upstream, "Upstream marked healthy");
+                }
+            }
+        }
+
+        self.status = status;
+    }
+}
+
+// Health is keyed by upstream origin (e.g. `http://127.0.0.1:3000`) and is
+// fed both by proxied requests and by active health checks. Each server keeps
+// its own, so that servers in the same process don't share ejections.
+#[derive(Debug, Default, Clone)]
+pub struct Health {
+    upstreams: Arc<Mutex<HashMap<String, UpstreamHealth>>>,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health::default()
+    }
+
+    pub fn record_success(&self, upstream: &str) {
+        let mut upstreams =
+            self.upstreams.lock().expect("Health lock poisoned.");
+        let health = upstreams.entry(String::from(upstream)).or_default();
+
+        health.consecutive_failures = 0;
+        health.consecutive_successes += 1;
+        health.mark(upstream, HealthStatus::Healthy);
+    }
+
+    // Only connection errors eject a target; a slow response says more about
+    // the request than about whether the target is up.
+    pub fn record_failure(&self, upstream: &str, error: &str, eject: bool) {
+        let mut upstreams =
+            self.upstreams.lock().expect("Health lock poisoned.");
+        let health = upstreams.entry(String::from(upstream)).or_default();
+
+        health.consecutive_failures += 1;
+        health.consecutive_successes = 0;
+        health.last_error = Some(String::from(error));
+
+        if eject {
+            health.mark(upstream, HealthStatus::Unhealthy);
+
+            // A target that's still down after being given another chance is
+            // ejected again.
+            health.ejected_at = Some(Instant::now());
+        }
+    }
+
+    fn record_check(
+        &self,
+        upstream: &str,
+        result: Result<(), String>,
+        config: &HealthCheckConfig,
+    ) {
+        let mut upstreams =
+            self.upstreams.lock().expect("Health lock poisoned.");
+        let health = upstreams.entry(String::from(upstream)).or_default();
+
+        match result {
+            Ok(()) => {
+                health.consecutive_failures = 0;
+                health.consecutive_successes += 1;
+
+                if health.consecutive_successes
+                    >= u64::from(config.healthy_threshold)
+                {
+                    health.mark(upstream, HealthStatus::Healthy);
+                }
+            }
+            Err(e) => {
+                health.consecutive_failures += 1;
+                health.consecutive_successes = 0;
+                health.last_error = Some(e);
+
+                if health.consecutive_failures
+                    >= u64::from(config.unhealthy_threshold)
+                {
+                    health.mark(upstream, HealthStatus::Unhealthy);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, upstream: &str) -> UpstreamHealth {
+        self.upstreams
+            .lock()
+            .expect("Health lock poisoned.")
+            .get(upstream)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Without active health checks, an ejected target is given another chance
+    // after the ejection time, so that it can recover from a restart.
+    fn is_available(
+        &self,
+        upstream: &str,
+        actively_checked: bool,
+        ejection_time: Duration,
+    ) -> bool {
+        let health = self.get(upstream);
+
+        match (health.status, health.ejected_at) {
+            (HealthStatus::Unhealthy, _) if actively_checked => false,
+            (HealthStatus::Unhealthy, Some(ejected_at)) => {
+                ejected_at.elapsed() >= ejection_time
+            }
+            _ => true,
+        }
+    }
+
+    // The targets of a proxy that should receive traffic, in order. When none
+    // are available they're all returned, as a request that might succeed is
+    // better than one that certainly won't.
+    pub fn available_targets(
+        &self,
+        proxy: &ProxyConfig,
+        settings: &Settings,
+    ) -> Vec<ProxyConfig> {
+        let targets = proxy.upstream_targets();
+        let ejection_time = Duration::from_secs(settings.ejection_time);
+
+        let available = targets
+            .iter()
+            .filter(|t| {
+                self.is_available(
+                    &t.upstream(&settings.host),
+                    t.health_check.is_some(),
+                    ejection_time,
+                )
+            })
+            .cloned()
+            .collect::<Vec<ProxyConfig>>();
+
+        match available.is_empty() {
+            true => targets,
+            false => available,
+        }
+    }
+}
+
+pub async fn check(
+    health: Health,
+    listeners: Vec<watch::Sender<Arc<Settings>>>,
+    shutdown: ShutdownHandle,
+) -> Result<(), Error> {
+    let Some(first) = listeners.first() else {
+        return Ok(());
+    };
+
+    let clients = Clients::new(&first.borrow());
+    let mut last_checked: HashMap<(String, String), Instant> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_TICK) => {}
+            _ = shutdown.requested() => break,
+        }
+
+        for listener in &listeners {
+            let settings = listener.borrow().clone();
+
+            for proxy in settings.proxies.iter().chain(settings.fallback.iter())
+            {
+                let Some(config) = &proxy.health_check else {
+                    continue;
+                };
+
+                for target in proxy.upstream_targets() {
+                    let upstream = target.upstream(&settings.host);
+                    let key = (upstream.clone(), config.path.clone());
+                    let interval = Duration::from_secs(config.interval);
+
+                    if last_checked
+                        .get(&key)
+                        .is_some_and(|checked| checked.elapsed() < interval)
+                    {
+                        continue;
+                    }
+
+                    last_checked.insert(key, Instant::now());
+
+                    let health = health.clone();
+                    let clients = clients.clone();
+                    let config = config.clone();
+                    let host = settings.host.clone();
+
+                    tokio::spawn(async move {
+                        let result =
+                            check_target(&clients, &target, &host, &config)
+                                .await;
+
+                        if let Err(e) = &result {
+                            tracing::debug!(
+                                %upstream,
+                                error = %e,
+                                "Health check failed"
+                            );
+                        }
+
+                        health.record_check(&upstream, result, &config);
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_target(
+    clients: &Clients,
+    target: &ProxyConfig,
+    host: &str,
+    config: &HealthCheckConfig,
+) -> Result<(), String> {
+    let client = clients.get(target).map_err(|e| e.to_string())?;
+
+    let authority =
+        format!("{}:{}", target.remote_host_or(host), target.remote_port);
+
+    let uri = Uri::builder()
+        .scheme(target.scheme.as_str())
+        .authority(authority.as_str())
+        .path_and_query(config.path.as_str())
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut req = Request::new(full(""));
+    *req.uri_mut() = uri;
+
+    if let Ok(authority) = HeaderValue::from_str(&authority) {
+        req.headers_mut().insert(hyper::header::HOST, authority);
+    }
+
+    let res = tokio::time::timeout(
+        Duration::from_secs(config.timeout),
+        client.request(req),
+    )
+    .await
+    .map_err(|_| format!("No response within {}s", config.timeout))?
+    .map_err(|e| e.to_string())?;
+
+    match res.status().is_success() || res.status().is_redirection() {
+        true => Ok(()),
+        false => Err(format!("Responded {}", res.status())),
+    }
+}
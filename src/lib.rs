@@ -1,4 +1,19 @@
+pub mod access_log;
+pub mod admin;
+pub mod affinity;
+pub mod balancer;
+pub mod ca;
+pub mod certs;
 pub mod cli;
+pub mod client;
 pub mod error;
+pub mod health;
+pub mod logging;
+pub mod metrics;
+pub mod reload;
+pub mod response;
+pub mod router;
 pub mod server;
 pub mod settings;
+pub mod shutdown;
+pub mod websocket;
@@ -0,0 +1,30 @@
+use crate::error::{Error, ParseError};
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+pub fn init(log_level: Option<&str>) -> Result<(), Error> {
+    // `--log-level` wins over `RUST_LOG`, which wins over the default.
+    let filter = match log_level {
+        Some(log_level) => EnvFilter::try_new(log_level).map_err(|_| {
+            Error::ParseError(ParseError::LogLevel(String::from(log_level)))
+        })?,
+        None => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL)),
+    };
+
+    // Diagnostics go to stderr so that stdout can be piped, e.g. a JSON
+    // access log into `jq`.
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(use_colour(&std::io::stderr()))
+        .init();
+
+    Ok(())
+}
+
+pub fn use_colour(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
@@ -0,0 +1,238 @@
+use crate::server;
+use hyper::{Method, StatusCode};
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+lazy_static! {
+    static ref REQUESTS: Mutex<BTreeMap<RequestLabels, Histogram>> =
+        Mutex::new(BTreeMap::new());
+}
+
+static ACTIVE_CONNECTIONS: AtomicI64 = AtomicI64::new(0);
+static ACTIVE_WEBSOCKETS: AtomicI64 = AtomicI64::new(0);
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestLabels {
+    pub route: String,
+    pub upstream: String,
+    pub method: String,
+    pub status: String,
+}
+
+impl RequestLabels {
+    pub fn new(
+        route: Option<&str>,
+        upstream: Option<&str>,
+        method: &Method,
+        status: StatusCode,
+    ) -> RequestLabels {
+        // Arbitrary methods would give unbounded label values.
+        let method = match *method {
+            Method::GET
+            | Method::HEAD
+            | Method::POST
+            | Method::PUT
+            | Method::PATCH
+            | Method::DELETE
+            | Method::OPTIONS
+            | Method::CONNECT
+            | Method::TRACE => method.as_str(),
+            _ => "OTHER",
+        };
+
+        RequestLabels {
+            route: String::from(route.unwrap_or("")),
+            upstream: String::from(upstream.unwrap_or("")),
+            method: String::from(method),
+            status: format!("{}xx", status.as_u16() / 100),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if value <= le {
+                *bucket += 1;
+            }
+        }
+
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+pub fn record_request(labels: RequestLabels, duration: Duration) {
+    REQUESTS
+        .lock()
+        .expect("Metrics lock poisoned.")
+        .entry(labels)
+        .or_default()
+        .observe(duration.as_secs_f64());
+}
+
+pub struct ActiveGuard(&'static AtomicI64);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn connection_opened() -> ActiveGuard {
+    ACTIVE_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    ActiveGuard(&ACTIVE_CONNECTIONS)
+}
+
+pub fn websocket_opened() -> ActiveGuard {
+    ACTIVE_WEBSOCKETS.fetch_add(1, Ordering::Relaxed);
+    ActiveGuard(&ACTIVE_WEBSOCKETS)
+}
+
+pub fn active_connections() -> i64 {
+    ACTIVE_CONNECTIONS.load(Ordering::Relaxed)
+}
+
+pub fn active_websockets() -> i64 {
+    ACTIVE_WEBSOCKETS.load(Ordering::Relaxed)
+}
+
+// Prometheus text exposition format, version 0.0.4.
+pub fn render() -> String {
+    let mut out = String::new();
+
+    let requests = REQUESTS.lock().expect("Metrics lock poisoned.");
+
+    header(
+        &mut out,
+        "joubini_requests_total",
+        "counter",
+        "Requests handled, by route, upstream, method and status class.",
+    );
+
+    for (labels, histogram) in requests.iter() {
+        let _ = writeln!(
+            out,
+            "joubini_requests_total{{{}}} {}",
+            format_labels(labels, None),
+            histogram.count
+        );
+    }
+
+    header(
+        &mut out,
+        "joubini_request_duration_seconds",
+        "histogram",
+        "Time from receiving a request to finishing its response.",
+    );
+
+    for (labels, histogram) in requests.iter() {
+        for (count, le) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "joubini_request_duration_seconds_bucket{{{}}} {}",
+                format_labels(labels, Some(&le.to_string())),
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "joubini_request_duration_seconds_bucket{{{}}} {}",
+            format_labels(labels, Some("+Inf")),
+            histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "joubini_request_duration_seconds_sum{{{}}} {}",
+            format_labels(labels, None),
+            histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "joubini_request_duration_seconds_count{{{}}} {}",
+            format_labels(labels, None),
+            histogram.count
+        );
+    }
+
+    drop(requests);
+
+    header(
+        &mut out,
+        "joubini_active_connections",
+        "gauge",
+        "Client connections currently open.",
+    );
+    let _ =
+        writeln!(out, "joubini_active_connections {}", active_connections());
+
+    header(
+        &mut out,
+        "joubini_active_websockets",
+        "gauge",
+        "Web socket tunnels currently open.",
+    );
+    let _ = writeln!(out, "joubini_active_websockets {}", active_websockets());
+
+    header(
+        &mut out,
+        "joubini_tls_handshake_failures_total",
+        "counter",
+        "TLS handshakes with clients that failed.",
+    );
+    let _ = writeln!(
+        out,
+        "joubini_tls_handshake_failures_total {}",
+        server::tls_handshake_failures()
+    );
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn format_labels(labels: &RequestLabels, le: Option<&str>) -> String {
+    let mut formatted = format!(
+        "route=\"{}\",upstream=\"{}\",method=\"{}\",status=\"{}\"",
+        escape(&labels.route),
+        escape(&labels.upstream),
+        labels.method,
+        labels.status
+    );
+
+    if let Some(le) = le {
+        let _ = write!(formatted, ",le=\"{}\"", le);
+    }
+
+    formatted
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,128 @@
+use crate::{
+    error::{Error, ReloadError},
+    server,
+    settings::{get_settings, Settings},
+};
+use std::{
+    ffi::OsString,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::watch;
+
+type Snapshot = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+pub async fn watch(
+    cli_args: Vec<OsString>,
+    listeners: Vec<watch::Sender<Arc<Settings>>>,
+    interval: Duration,
+) {
+    let mut files = snapshot(&listeners);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let latest = snapshot(&listeners);
+
+        if latest == files {
+            continue;
+        }
+
+        match reload(&cli_args, &listeners) {
+            Ok(()) => tracing::info!("Reloaded configuration"),
+            Err(e) => {
+                tracing::error!(error = %e, "Ignoring invalid configuration")
+            }
+        }
+
+        // Take the snapshot again as the new settings may refer to other files.
+        files = snapshot(&listeners);
+    }
+}
+
+pub fn reload(
+    cli_args: &[OsString],
+    listeners: &[watch::Sender<Arc<Settings>>],
+) -> Result<(), Error> {
+    let mut listener_settings =
+        get_settings(cli_args.to_vec())?.listener_settings();
+
+    let unchanged = listener_settings.len() == listeners.len()
+        && listener_settings
+            .iter()
+            .zip(listeners)
+            .all(|(new, current)| {
+                let current = current.borrow();
+
+                // Port 0 was bound to whichever port the OS picked.
+                new.host == current.host
+                    && (new.local_port == 0
+                        || new.local_port == current.local_port)
+            });
+
+    if !unchanged {
+        return Err(Error::ReloadError(ReloadError::ListenersChanged));
+    }
+
+    // Routes changed through the admin API aren't in the config file, so
+    // they're changed again on top of it.
+    for (settings, listener) in listener_settings.iter_mut().zip(listeners) {
+        let current = listener.borrow();
+
+        settings.local_port = current.local_port;
+        settings.replay_route_edits(&current.route_edits);
+
+        if !current.route_edits.is_empty() {
+            tracing::info!(
+                host = %settings.host,
+                port = settings.local_port,
+                edits = current.route_edits.len(),
+                "Keeping routes changed through the admin API"
+            );
+        }
+    }
+
+    for settings in &listener_settings {
+        server::validate(settings)?;
+    }
+
+    for (settings, listener) in listener_settings.into_iter().zip(listeners) {
+        listener.send_replace(Arc::new(settings));
+    }
+
+    Ok(())
+}
+
+fn watched_files(settings: &Settings) -> Vec<PathBuf> {
+    settings
+        .config
+        .iter()
+        .chain(settings.pem.iter())
+        .chain(settings.key.iter())
+        .chain(settings.certificates.iter().flat_map(|c| [&c.pem, &c.key]))
+        .cloned()
+        .collect()
+}
+
+fn snapshot(listeners: &[watch::Sender<Arc<Settings>>]) -> Snapshot {
+    let mut files = listeners
+        .iter()
+        .flat_map(|l| watched_files(&l.borrow()))
+        .collect::<Vec<PathBuf>>();
+
+    files.sort();
+    files.dedup();
+
+    files
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|m| Ok((m.modified()?, m.len())))
+                .ok();
+
+            (path, modified)
+        })
+        .collect()
+}
@@ -0,0 +1,124 @@
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    header::{self, HeaderValue},
+    Response, StatusCode,
+};
+
+pub type ProxyBody = BoxBody<Bytes, hyper::Error>;
+
+#[derive(Debug, serde::Serialize)]
+pub struct ErrorPage {
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+}
+
+impl ErrorPage {
+    pub fn new(status: StatusCode, message: &str) -> ErrorPage {
+        ErrorPage {
+            status: status.as_u16(),
+            error: String::from(status.canonical_reason().unwrap_or("")),
+            message: String::from(message),
+            route: None,
+            upstream: None,
+            routes: vec![],
+        }
+    }
+
+    pub fn into_response(
+        self,
+        accept: Option<&HeaderValue>,
+    ) -> Response<ProxyBody> {
+        let (content_type, body) = if prefers_json(accept) {
+            (
+                "application/json",
+                serde_json::to_string(&self)
+                    .expect("Error page should be serialisable to JSON."),
+            )
+        } else {
+            ("text/html; charset=utf-8", self.to_html())
+        };
+
+        let mut res = Response::new(full(body));
+
+        *res.status_mut() = StatusCode::from_u16(self.status)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(content_type),
+        );
+
+        res
+    }
+
+    fn to_html(&self) -> String {
+        let title = escape_html(&format!("{} {}", self.status, self.error));
+
+        let mut details = String::new();
+
+        if let Some(route) = &self.route {
+            details.push_str(&format!(
+                "<dt>Route</dt><dd><code>{}</code></dd>",
+                escape_html(route)
+            ));
+        }
+
+        if let Some(upstream) = &self.upstream {
+            details.push_str(&format!(
+                "<dt>Upstream</dt><dd><code>{}</code></dd>",
+                escape_html(upstream)
+            ));
+        }
+
+        if !self.routes.is_empty() {
+            details.push_str(&format!(
+                "<dt>Routes</dt><dd><ul>{}</ul></dd>",
+                self.routes
+                    .iter()
+                    .map(|r| format!(
+                        "<li><code>{}</code></li>",
+                        escape_html(r)
+                    ))
+                    .collect::<String>()
+            ));
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html><head><title>{}</title></head><body><h1>{}</h1><p>{}</p><dl>{}</dl><hr><p>joubini</p></body></html>\n",
+            title,
+            title,
+            escape_html(&self.message),
+            details
+        )
+    }
+}
+
+pub fn full<T: Into<Bytes>>(chunk: T) -> ProxyBody {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn prefers_json(accept: Option<&HeaderValue>) -> bool {
+    accept.and_then(|a| a.to_str().ok()).is_some_and(|a| {
+        a.contains("application/json") && !a.contains("text/html")
+    })
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,81 @@
+use crate::settings::ProxyConfig;
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+pub fn matches(local_path: &str, path: &str) -> bool {
+    strip_prefix(local_path, path).is_some()
+}
+
+pub fn strip_prefix<'a>(local_path: &str, path: &'a str) -> Option<&'a str> {
+    segments(local_path).try_fold(path, |rest, segment| {
+        rest.trim_start_matches('/')
+            .strip_prefix(segment)
+            .filter(|r| r.is_empty() || r.starts_with('/'))
+    })
+}
+
+pub fn matches_host(local_host: &str, host: &str) -> bool {
+    match local_host.strip_prefix("*.") {
+        Some(suffix) => host
+            .strip_suffix(suffix)
+            .and_then(|h| h.strip_suffix('.'))
+            .is_some_and(|h| !h.is_empty()),
+        None => local_host == host,
+    }
+}
+
+// Exact hosts beat wildcards, longer wildcards beat shorter ones, and any
+// host beats proxies that don't specify one.
+fn host_specificity(
+    local_host: Option<&str>,
+    host: Option<&str>,
+) -> Option<usize> {
+    match (local_host, host) {
+        (None, _) => Some(0),
+        (Some(local_host), Some(host)) if matches_host(local_host, host) => {
+            match local_host.starts_with("*.") {
+                true => Some(local_host.len()),
+                false => Some(usize::MAX),
+            }
+        }
+        _ => None,
+    }
+}
+
+pub fn find_proxy<'a>(
+    host: Option<&str>,
+    path: &str,
+    proxies: &'a [ProxyConfig],
+) -> Option<&'a ProxyConfig> {
+    // `max_by_key` returns the last of equally specific proxies, so when the
+    // same route is declared more than once the last declaration wins.
+    proxies
+        .iter()
+        .filter(|p| matches(&p.local_path, path))
+        .filter_map(|p| {
+            host_specificity(p.local_host.as_deref(), host).map(|h| (p, h))
+        })
+        .max_by_key(|(p, h)| (*h, segments(&p.local_path).count()))
+        .map(|(p, _)| p)
+}
+
+pub fn shadowed_proxies(
+    proxies: &[ProxyConfig],
+) -> Vec<(&ProxyConfig, &ProxyConfig)> {
+    proxies
+        .iter()
+        .enumerate()
+        .filter_map(|(i, proxy)| {
+            proxies[i + 1..]
+                .iter()
+                .rfind(|p| {
+                    p.local_host == proxy.local_host
+                        && segments(&p.local_path)
+                            .eq(segments(&proxy.local_path))
+                })
+                .map(|p| (proxy, p))
+        })
+        .collect()
+}
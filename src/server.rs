@@ -1,16 +1,41 @@
 use crate::{
-    error::Error,
-    settings::{ProxyConfig, Settings},
+    access_log::{AccessLog, AccessLogEntry, CountedBody, LoggedBody},
+    admin, affinity,
+    balancer::{self, Balancer, TrackedBody},
+    ca::LocalCa,
+    certs::CertStore,
+    client::{self, Clients, HttpClient},
+    error::{Error, ProxyError, TlsError},
+    health::{self, Health},
+    metrics::{self, RequestLabels},
+    response::{full, ErrorPage, ProxyBody},
+    router,
+    settings::{
+        display_targets, validate_fallback, ProxyConfig, Scheme, Settings,
+        UpstreamProtocol, UpstreamTls,
+    },
+    shutdown::ShutdownHandle,
+    websocket,
 };
 use hyper::{
-    client::conn::http1::SendRequest,
     header::{HeaderName, HeaderValue},
-    HeaderMap, Uri,
+    http::uri::Authority,
+    HeaderMap, StatusCode, Uri, Version,
 };
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use lazy_static::lazy_static;
-use native_tls::Identity;
-use std::{fs, path::PathBuf, str::FromStr, sync::Arc};
+use rustls::server::ResolvesServerCert;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+use tokio_rustls::TlsAcceptor;
+use tracing::{field, Instrument, Span};
 
 lazy_static! {
     static ref HOST_HEADER_NAME: HeaderName = HeaderName::from_static("host");
@@ -18,177 +43,1022 @@ lazy_static! {
         HeaderName::from_static("x-forwarded-for");
 }
 
-use http_body_util::{combinators::BoxBody, BodyExt};
-use hyper::{body::Incoming, service::service_fn, Request, Response};
-use tokio::net::{TcpListener, TcpStream};
+static TLS_HANDSHAKE_FAILURES: AtomicU64 = AtomicU64::new(0);
+static REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+pub fn tls_handshake_failures() -> u64 {
+    TLS_HANDSHAKE_FAILURES.load(Ordering::Relaxed)
+}
+
+use http_body_util::BodyExt;
+use hyper::{
+    body::{Body, Incoming},
+    service::service_fn,
+    Request, Response,
+};
+use tokio::{
+    net::TcpListener,
+    sync::watch,
+    task::{JoinHandle, JoinSet},
+};
+
+pub struct ProxyServer;
+
+impl ProxyServer {
+    pub fn builder() -> ProxyServerBuilder {
+        ProxyServerBuilder::default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProxyServerBuilder {
+    settings: Settings,
+    shutdown: Option<ShutdownHandle>,
+}
+
+impl ProxyServerBuilder {
+    pub fn settings(mut self, settings: Settings) -> ProxyServerBuilder {
+        self.settings = settings;
+        self
+    }
+
+    pub fn shutdown_handle(
+        mut self,
+        shutdown: ShutdownHandle,
+    ) -> ProxyServerBuilder {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub async fn bind(self) -> Result<ProxyServerHandle, Error> {
+        let shutdown = self.shutdown.unwrap_or_default();
+        let health = Health::new();
+        let balancer = Balancer::new();
+        let mut bound = vec![];
+
+        // Bind and validate every listener before serving any of them, so
+        // that a bad listener fails here rather than in a background task.
+        for mut settings in self.settings.listener_settings() {
+            let listener = TcpListener::bind(build_addr(
+                &settings.host,
+                settings.local_port,
+            ))
+            .await?;
+
+            let local_addr = listener.local_addr()?;
+
+            // Port 0 asks the OS for a free port, which is the one that
+            // forwarded requests should report.
+            settings.local_port = local_addr.port();
+
+            validate(&settings)?;
+
+            bound.push((listener, local_addr, settings));
+        }
+
+        // The admin API can change routes without authentication, so it's only
+        // reachable from elsewhere when a host is given explicitly.
+        let admin_listener = match &self.settings.admin {
+            Some(admin) => Some(
+                TcpListener::bind(build_addr(
+                    admin.host.as_deref().unwrap_or("127.0.0.1"),
+                    admin.port,
+                ))
+                .await?,
+            ),
+            None => None,
+        };
+
+        let mut local_addrs = vec![];
+        let mut settings_txs = vec![];
+        let mut tasks = vec![];
+
+        for (listener, local_addr, settings) in bound {
+            let (settings_tx, settings_rx) = watch::channel(Arc::new(settings));
+
+            tasks.push(tokio::task::spawn(serve(
+                Arc::new(listener),
+                settings_rx,
+                health.clone(),
+                balancer.clone(),
+                shutdown.clone(),
+            )));
+
+            local_addrs.push(local_addr);
+            settings_txs.push(settings_tx);
+        }
+
+        tasks.push(tokio::task::spawn(health::check(
+            health.clone(),
+            settings_txs.clone(),
+            shutdown.clone(),
+        )));
+
+        let admin_addr = match admin_listener {
+            Some(listener) => {
+                let admin_addr = listener.local_addr()?;
+
+                tasks.push(tokio::task::spawn(admin::serve(
+                    Arc::new(listener),
+                    settings_txs.clone(),
+                    health.clone(),
+                    balancer.clone(),
+                    shutdown.clone(),
+                )));
+
+                Some(admin_addr)
+            }
+            None => None,
+        };
+
+        Ok(ProxyServerHandle {
+            local_addrs,
+            admin_addr,
+            settings_txs,
+            health,
+            balancer,
+            shutdown,
+            tasks,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ProxyServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    admin_addr: Option<SocketAddr>,
+    settings_txs: Vec<watch::Sender<Arc<Settings>>>,
+    health: Health,
+    balancer: Balancer,
+    shutdown: ShutdownHandle,
+    tasks: Vec<JoinHandle<Result<(), Error>>>,
+}
+
+impl ProxyServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.admin_addr
+    }
+
+    pub fn settings_senders(&self) -> Vec<watch::Sender<Arc<Settings>>> {
+        self.settings_txs.clone()
+    }
+
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    pub fn balancer(&self) -> Balancer {
+        self.balancer.clone()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+
+    pub async fn join(self) -> Result<(), Error> {
+        let mut tasks = JoinSet::new();
+
+        for task in self.tasks {
+            tasks.spawn(task);
+        }
+
+        let mut result = Ok(());
+
+        while let Some(task_result) = tasks.join_next().await {
+            let task_result = task_result
+                .and_then(|r| r)
+                .expect("Listener task should not panic.");
+
+            // A listener that fails takes the others down with it, rather
+            // than leaving the server half running.
+            if let Err(e) = task_result {
+                self.shutdown.shutdown();
+
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+}
 
 pub async fn start(
     listener: Arc<TcpListener>,
     settings: Arc<Settings>,
 ) -> Result<(), Error> {
-    println!("Listening on: {}", listener.local_addr()?);
-    println!("{}", settings);
-
-    match settings.tls {
-        true => {
-            let pem = fs::read(
-                settings
-                    .pem
-                    .as_ref()
-                    .unwrap_or(&PathBuf::from_str("").unwrap()),
-            )?;
-            let key = fs::read(
-                settings
-                    .key
-                    .as_ref()
-                    .unwrap_or(&PathBuf::from_str("").unwrap()),
-            )?;
-
-            let cert = Identity::from_pkcs8(&pem, &key).unwrap();
-
-            let tls_acceptor =
-                native_tls::TlsAcceptor::builder(cert).build().unwrap();
-
-            let tls_acceptor =
-                tokio_native_tls::TlsAcceptor::from(tls_acceptor);
-
-            loop {
-                let settings = settings.clone();
-                let (stream, _) = listener.clone().accept().await?;
-
-                // spawn_tls_server(tls_acceptor.clone(), stream, settings);
-                let tls_stream =
-                    tls_acceptor.accept(stream).await.expect("accept error");
-                let io = hyper_util::rt::TokioIo::new(tls_stream);
-                spawn_server(io, settings)
+    start_with_shutdown(listener, settings, ShutdownHandle::new()).await
+}
+
+pub async fn start_with_shutdown(
+    listener: Arc<TcpListener>,
+    settings: Arc<Settings>,
+    shutdown: ShutdownHandle,
+) -> Result<(), Error> {
+    let (settings_tx, settings_rx) = watch::channel(settings);
+    let health = Health::new();
+
+    let checker = tokio::task::spawn(health::check(
+        health.clone(),
+        vec![settings_tx],
+        shutdown.clone(),
+    ));
+
+    let result =
+        serve(listener, settings_rx, health, Balancer::new(), shutdown).await;
+
+    checker.abort();
+
+    result
+}
+
+// Serves a single listener; active health checks for `health` are left to
+// the caller, as in `start_with_shutdown` and `ProxyServerBuilder::bind`.
+pub async fn serve(
+    listener: Arc<TcpListener>,
+    mut settings_rx: watch::Receiver<Arc<Settings>>,
+    health: Health,
+    balancer: Balancer,
+    shutdown: ShutdownHandle,
+) -> Result<(), Error> {
+    let local_addr = listener.local_addr()?;
+
+    tracing::info!(%local_addr, "Listening");
+
+    let (state_tx, state_rx) = watch::channel(Arc::new(ListenerState::new(
+        settings_rx.borrow_and_update().clone(),
+        health.clone(),
+        balancer.clone(),
+    )?));
+
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            // Settings are applied as soon as they change, rather than when
+            // the next connection is accepted.
+            Ok(()) = settings_rx.changed() => {
+                let settings = settings_rx.borrow_and_update().clone();
+
+                match ListenerState::new(
+                    settings,
+                    health.clone(),
+                    balancer.clone(),
+                ) {
+                    Ok(state) => {
+                        state_tx.send_replace(Arc::new(state));
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            error = %e,
+                            "Unable to apply new settings"
+                        )
+                    }
+                }
+
+                continue;
+            }
+            _ = shutdown.requested() => break,
+        };
+
+        let settings = settings_rx.clone();
+        let state = state_rx.clone();
+        let tls_acceptor = state_rx.borrow().tls_acceptor.clone();
+        let connection_shutdown = shutdown.clone();
+        let span = tracing::info_span!("connection", %remote_addr, %local_addr);
+        let active = metrics::connection_opened();
+
+        match tls_acceptor {
+            Some(tls_acceptor) => {
+                // Handshake in the connection's own task so that a slow or
+                // failing client can't hold up the accept loop.
+                let connection = async move {
+                    let _active = active;
+
+                    match tls_acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            let io = TokioIo::new(tls_stream);
+                            serve_connection(
+                                io,
+                                settings,
+                                state,
+                                connection_shutdown,
+                                remote_addr,
+                            )
+                            .await;
+                        }
+                        Err(e) => tls_handshake_failed(e),
+                    }
+                };
+
+                shutdown.spawn(connection.instrument(span));
+            }
+            None => {
+                let connection = async move {
+                    let _active = active;
+
+                    serve_connection(
+                        TokioIo::new(stream),
+                        settings,
+                        state,
+                        connection_shutdown,
+                        remote_addr,
+                    )
+                    .await;
+                };
+
+                shutdown.spawn(connection.instrument(span));
             }
         }
-        false => loop {
-            let settings = settings.clone();
-            let (stream, _) = listener.clone().accept().await?;
-            let io = hyper_util::rt::TokioIo::new(stream);
+    }
+
+    drop(listener);
+
+    tracing::info!(
+        %local_addr,
+        connections = shutdown.active_connections(),
+        "Stopped listening, draining connections"
+    );
 
-            spawn_server(io, settings);
-        },
+    let shutdown_timeout = state_rx.borrow().settings.shutdown_timeout;
+
+    if !shutdown.drain(Duration::from_secs(shutdown_timeout)).await {
+        tracing::warn!(
+            shutdown_timeout,
+            "Closed connections still open after the shutdown timeout"
+        );
     }
+
+    Ok(())
 }
 
-fn spawn_server(
+pub fn validate(settings: &Settings) -> Result<(), Error> {
+    AccessLog::new(&settings.access_log)?;
+
+    if let Some(fallback) = &settings.fallback {
+        validate_fallback(fallback)?;
+    }
+
+    // Upstream TLS is otherwise only set up on the first request that needs
+    // it, so a bad CA bundle would fail requests rather than the settings.
+    let mut upstream_tls = settings
+        .proxies
+        .iter()
+        .chain(settings.fallback.iter())
+        .map(|proxy| &proxy.tls)
+        .filter(|tls| tls.ca.is_some())
+        .collect::<Vec<&UpstreamTls>>();
+
+    upstream_tls.sort();
+    upstream_tls.dedup();
+
+    for tls in upstream_tls {
+        client::build_tls_config(tls)?;
+    }
+
+    if settings.tls {
+        build_tls_acceptor(settings)?;
+    }
+
+    Ok(())
+}
+
+struct ListenerState {
+    settings: Arc<Settings>,
+    clients: Clients,
+    health: Health,
+    balancer: Balancer,
+    access_log: Arc<AccessLog>,
+    tls_acceptor: Option<TlsAcceptor>,
+}
+
+impl ListenerState {
+    fn new(
+        settings: Arc<Settings>,
+        health: Health,
+        balancer: Balancer,
+    ) -> Result<ListenerState, Error> {
+        let tls_acceptor = match settings.tls {
+            true => Some(build_tls_acceptor(&settings)?),
+            false => None,
+        };
+
+        for proxy in &settings.proxies {
+            tracing::info!(
+                host = %settings.host,
+                port = settings.local_port,
+                "Proxying {}{} -> {}",
+                proxy.local_host.as_deref().unwrap_or(""),
+                proxy.local_path,
+                display_targets(proxy)
+            );
+        }
+
+        for (shadowed, by) in router::shadowed_proxies(&settings.proxies) {
+            tracing::warn!(
+                %shadowed,
+                %by,
+                "Proxy is shadowed by another and will never be used"
+            );
+        }
+
+        Ok(ListenerState {
+            clients: Clients::new(&settings),
+            health,
+            balancer,
+            access_log: Arc::new(AccessLog::new(&settings.access_log)?),
+            settings,
+            tls_acceptor,
+        })
+    }
+}
+
+fn build_tls_acceptor(settings: &Settings) -> Result<TlsAcceptor, Error> {
+    let resolver: Arc<dyn ResolvesServerCert> =
+        match (settings.pem.as_deref(), settings.key.as_deref()) {
+            (Some(pem), Some(key)) => Arc::new(CertStore::load(
+                &settings.certificates,
+                Some((pem, key)),
+            )?),
+            (None, None) if !settings.certificates.is_empty() => {
+                Arc::new(CertStore::load(&settings.certificates, None)?)
+            }
+            (None, None) => {
+                let ca = LocalCa::load_or_create(settings.ca_dir.as_deref())?;
+
+                tracing::info!(
+                    ca_dir = %ca.dir().display(),
+                    "Issuing certificates from local CA"
+                );
+
+                Arc::new(ca)
+            }
+            _ => {
+                return Err(Error::TlsError(TlsError::MissingCertificateOrKey))
+            }
+        };
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn tls_handshake_failed(e: impl std::fmt::Display) {
+    let failures = TLS_HANDSHAKE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+
+    tracing::warn!(failures, error = %e, "TLS handshake failed");
+}
+
+async fn serve_connection(
     io_stream: impl hyper::rt::Read
         + hyper::rt::Write
         + std::marker::Unpin
         + std::marker::Send
         + 'static,
-    settings: Arc<Settings>,
+    settings: watch::Receiver<Arc<Settings>>,
+    state: watch::Receiver<Arc<ListenerState>>,
+    shutdown: ShutdownHandle,
+    remote_addr: SocketAddr,
 ) {
-    tokio::task::spawn(async move {
-        if let Err(e) =
-            hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                .serve_connection(
-                    io_stream,
-                    service_fn(move |req| handle(req, settings.clone())),
-                )
-                .await
-        {
-            eprintln!("\x1b[31mERR\x1b[0m Error serving connection: {}", e);
+    let mut builder =
+        hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
+    builder.http2().enable_connect_protocol();
+
+    // HTTP/2 streams are handled on their own tasks, so the connection span
+    // is passed along explicitly rather than relying on the current span.
+    let connection_span = Span::current();
+    let service_shutdown = shutdown.clone();
+    let connection = builder.serve_connection_with_upgrades(
+        io_stream,
+        service_fn(move |req| {
+            // Each request uses the latest settings, so route changes apply to
+            // connections that are already open, while requests in flight
+            // finish on the settings they started with. The clients and access
+            // log follow once the listener has rebuilt them.
+            handle(
+                req,
+                settings.borrow().clone(),
+                state.borrow().clone(),
+                service_shutdown.clone(),
+                remote_addr,
+                connection_span.clone(),
+            )
+        }),
+    );
+    tokio::pin!(connection);
+
+    // Once shutdown is requested, in-flight requests are allowed to finish
+    // but idle keep-alive connections are closed straight away.
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = shutdown.requested() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
         }
-    });
+    };
+
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "Error serving connection");
+    }
 }
 
 async fn handle(
     req: Request<Incoming>,
     settings: Arc<Settings>,
-) -> Result<Response<BoxBody<hyper::body::Bytes, hyper::Error>>, Error> {
-    let proxy = get_proxy(req.uri().path().to_string(), &settings.proxies);
+    state: Arc<ListenerState>,
+    shutdown: ShutdownHandle,
+    remote_addr: SocketAddr,
+    connection_span: Span,
+) -> Result<Response<ProxyBody>, Error> {
+    let started = Instant::now();
+    let bytes_in = Arc::new(AtomicU64::new(0));
+    let request_id = REQUEST_ID.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let span = tracing::info_span!(
+        parent: &connection_span,
+        "request",
+        id = request_id,
+        method = %req.method(),
+        uri = %req.uri(),
+        route = field::Empty,
+        upstream = field::Empty,
+    );
 
-    let addr = build_addr(&settings.host, proxy.remote_port);
+    let mut entry = AccessLogEntry {
+        request_id,
+        timestamp: OffsetDateTime::now_utc(),
+        client_addr: remote_addr,
+        method: req.method().to_string(),
+        uri: req
+            .uri()
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| String::from("/")),
+        version: format!("{:?}", req.version()),
+        host: request_host(&req),
+        status: 0,
+        duration_ms: 0.0,
+        bytes_in: 0,
+        bytes_out: 0,
+        route: None,
+        upstream: None,
+        redirect: None,
+        error: None,
+        referer: header_string(req.headers(), hyper::header::REFERER),
+        user_agent: header_string(req.headers(), hyper::header::USER_AGENT),
+    };
 
-    let stream = TcpStream::connect(addr).await?;
+    let method = req.method().clone();
 
-    let io = hyper_util::rt::TokioIo::new(stream);
+    let (res, proxy) = route(
+        req,
+        &settings,
+        &state,
+        &shutdown,
+        &mut entry,
+        bytes_in.clone(),
+    )
+    .instrument(span.clone())
+    .await;
 
-    let (client, connection) = hyper::client::conn::http1::Builder::new()
-        .handshake(io)
-        .await?;
+    entry.status = res.status().as_u16();
 
-    tokio::task::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!(
-                "\x1b[31mERR\x1b[0m Unable to establish connection: {:?}",
-                e
-            );
-        }
-    });
+    span.in_scope(|| tracing::debug!(status = entry.status, "Responded"));
 
+    let labels = RequestLabels::new(
+        proxy.as_ref().map(|p| p.local_path.as_str()),
+        proxy
+            .as_ref()
+            .map(|p| p.upstream(&settings.host))
+            .as_deref(),
+        &method,
+        res.status(),
+    );
+
+    Ok(res.map(|body| {
+        LoggedBody::new(
+            body,
+            entry,
+            labels,
+            started,
+            bytes_in,
+            state.access_log.clone(),
+        )
+        .boxed()
+    }))
+}
+
+async fn route(
+    mut req: Request<Incoming>,
+    settings: &Settings,
+    state: &ListenerState,
+    shutdown: &ShutdownHandle,
+    entry: &mut AccessLogEntry,
+    bytes_in: Arc<AtomicU64>,
+) -> (Response<ProxyBody>, Option<ProxyConfig>) {
     let request_uri = req.uri().clone();
-    let request_method = req.method().clone();
+    let accept = req.headers().get(hyper::header::ACCEPT).cloned();
+
+    if let Some(https_port) = settings.redirect_to_https {
+        let res = redirect_to_https(
+            entry.host.as_deref().unwrap_or(&settings.host),
+            https_port,
+            &request_uri,
+        );
+
+        entry.redirect = res
+            .headers()
+            .get(hyper::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .map(String::from);
+
+        return (res, None);
+    }
+
+    let Some(proxy) = router::find_proxy(
+        entry.host.as_deref(),
+        req.uri().path(),
+        &settings.proxies,
+    )
+    .or(settings.fallback.as_ref()) else {
+        entry.error = Some(String::from("no matching proxy"));
+
+        let res = not_found(settings, request_uri.path(), accept.as_ref());
+
+        return (res, None);
+    };
+
+    entry.route = Some(proxy.to_string());
+    Span::current().record("route", field::display(proxy));
+
+    let upgrade = websocket::websocket_upgrade(&req);
+    let downstream_upgrade = upgrade.map(|_| hyper::upgrade::on(&mut req));
+
+    let pinned = affinity::pinned(proxy, req.headers());
+
+    let mut targets = state.balancer.order(
+        proxy,
+        state.health.available_targets(proxy, settings),
+        &settings.host,
+        &req,
+        entry.client_addr,
+    );
+
+    // A client pinned to a target that's no longer available falls back to
+    // the load balancer, and is pinned to wherever it ends up.
+    if let Some(pinned) = &pinned {
+        balancer::prefer(&mut targets, pinned, &settings.host);
+    }
+
+    let mut targets = targets.into_iter();
+    let mut target = targets
+        .next()
+        .expect("A proxy should always have at least one target.");
+
+    // Requests without a body can be sent again, so they fail over to the
+    // next target when a connection to one can't be made.
+    let replay = (upgrade.is_none() && req.body().is_end_stream())
+        .then(|| clone_request(&req));
+
+    let mut proxy_request = match build_request(
+        req,
+        &settings.host,
+        settings.local_port,
+        &target,
+    ) {
+        Ok(proxy_request) => {
+            proxy_request.map(|b| CountedBody::new(b, bytes_in).boxed())
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "Unable to build request");
+
+            entry.error = Some(String::from("invalid request"));
+
+            let mut page =
+                ErrorPage::new(StatusCode::BAD_REQUEST, &e.to_string());
+            page.route = Some(proxy.local_path.clone());
+
+            return (page.into_response(accept.as_ref()), Some(target));
+        }
+    };
+
+    let (result, proxy_uri, active) = loop {
+        let proxy_uri = proxy_request.uri().clone();
+        let upstream = target.upstream(&settings.host);
+
+        entry.upstream = Some(proxy_uri.to_string());
+        Span::current().record("upstream", field::display(&proxy_uri));
+
+        tracing::debug!("Forwarding request");
+
+        let active = state.balancer.request_started(&upstream);
+        let result =
+            forward(&state.clients, &target, settings, proxy_request).await;
+
+        match &result {
+            Ok(_) => state.health.record_success(&upstream),
+            Err(e) => state.health.record_failure(
+                &upstream,
+                &e.to_string(),
+                is_connect_error(e),
+            ),
+        }
+
+        let next = match (&result, &replay) {
+            (Err(e), Some(replay)) if is_connect_error(e) => {
+                targets.next().map(|next| (e, replay, next))
+            }
+            _ => None,
+        };
+
+        let Some((e, replay, next)) = next else {
+            break (result, proxy_uri, active);
+        };
+
+        tracing::warn!(
+            error = %e,
+            next = %next.upstream(&settings.host),
+            "Failing over to next upstream target"
+        );
+
+        match build_request(
+            clone_request(replay),
+            &settings.host,
+            settings.local_port,
+            &next,
+        ) {
+            Ok(next_request) => {
+                proxy_request = next_request.map(|()| full(""));
+                target = next;
+            }
+            Err(_) => break (result, proxy_uri, active),
+        }
+    };
+
+    let upstream = target.upstream(&settings.host);
+
+    let res = match result {
+        Ok(mut res) => {
+            if pinned.as_deref() != Some(upstream.as_str()) {
+                if let Some(cookie) = affinity::set_cookie(proxy, &upstream) {
+                    res.headers_mut().append(hyper::header::SET_COOKIE, cookie);
+                }
+            }
+
+            match (upgrade, downstream_upgrade) {
+                (Some(upgrade), Some(downstream_upgrade))
+                    if res.status() == StatusCode::SWITCHING_PROTOCOLS =>
+                {
+                    websocket::tunnel(
+                        upgrade,
+                        downstream_upgrade,
+                        res,
+                        active,
+                        shutdown,
+                    )
+                }
+                _ => res.map(|b| TrackedBody::new(b, active).boxed()),
+            }
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Request to upstream failed");
+
+            entry.error = Some(e.to_string());
 
-    let proxy_request =
-        build_request(req, &settings.host, settings.local_port, proxy)?;
+            let mut page =
+                ErrorPage::new(gateway_error_status(&e), &e.to_string());
+            page.route = Some(proxy.local_path.clone());
+            page.upstream = Some(proxy_uri.to_string());
 
-    let proxy_uri = proxy_request.uri().clone();
+            page.into_response(accept.as_ref())
+        }
+    };
+
+    (res, Some(target))
+}
+
+fn clone_request<B>(req: &Request<B>) -> Request<()> {
+    let mut clone = Request::new(());
+
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.version_mut() = req.version();
+    *clone.headers_mut() = req.headers().clone();
+
+    clone
+}
+
+fn is_connect_error(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::ProxyError(ProxyError::UpstreamRequestFailed(e)) if e.is_connect()
+    )
+}
+
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+fn request_host(req: &Request<Incoming>) -> Option<String> {
+    // HTTP/2 requests carry the host in the `:authority` pseudo-header, which
+    // hyper exposes as the URI authority.
+    let host = match req.uri().host() {
+        Some(host) => String::from(host),
+        None => req
+            .headers()
+            .get(hyper::header::HOST)?
+            .to_str()
+            .ok()?
+            .parse::<Authority>()
+            .ok()?
+            .host()
+            .to_string(),
+    };
+
+    Some(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+fn redirect_to_https(
+    host: &str,
+    https_port: u16,
+    request_uri: &Uri,
+) -> Response<ProxyBody> {
+    let authority = match https_port {
+        443 => String::from(host),
+        _ => build_addr(host, https_port),
+    };
+
+    let path_and_query = request_uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
 
-    let res = send_request(client, proxy_request).await?;
-    let status = res.status().as_u16();
+    let mut res = Response::new(full(""));
 
-    println!(
-        "{} {} {} \x1b[94m➡\x1b[0m :{}{}",
-        colourise_status(status),
-        request_method,
-        request_uri.path(),
-        proxy.remote_port,
-        proxy_uri.path(),
+    // 308 rather than 301 so that clients keep the method and body.
+    *res.status_mut() = StatusCode::PERMANENT_REDIRECT;
+
+    if let Ok(location) = HeaderValue::from_str(&format!(
+        "https://{}{}",
+        authority, path_and_query
+    )) {
+        res.headers_mut().insert(hyper::header::LOCATION, location);
+    }
+
+    res
+}
+
+fn not_found(
+    settings: &Settings,
+    path: &str,
+    accept: Option<&HeaderValue>,
+) -> Response<ProxyBody> {
+    let mut page = ErrorPage::new(
+        StatusCode::NOT_FOUND,
+        &format!("No proxy configured for {}", path),
     );
 
-    Ok(res.map(|b| b.boxed()))
+    if settings.dev {
+        page.routes = settings.proxies.iter().map(|p| p.to_string()).collect();
+    }
+
+    page.into_response(accept)
+}
+
+async fn forward(
+    clients: &Clients,
+    proxy: &ProxyConfig,
+    settings: &Settings,
+    proxy_request: Request<ProxyBody>,
+) -> Result<Response<Incoming>, Error> {
+    let client = match websocket::websocket_upgrade(&proxy_request) {
+        Some(_) => clients.get_with_protocol(proxy, UpstreamProtocol::Http1)?,
+        None => clients.get(proxy)?,
+    };
+
+    let response_timeout = Duration::from_secs(settings.response_timeout);
+
+    tokio::time::timeout(response_timeout, send_request(&client, proxy_request))
+        .await
+        .map_err(|_| {
+            Error::ProxyError(ProxyError::UpstreamTimeout(
+                settings.response_timeout,
+            ))
+        })?
 }
 
-fn colourise_status(status_code: u16) -> String {
-    match status_code {
-        200..=399 => format!("\x1b[92m{}\x1b[0m", status_code),
-        400..=499 => format!("\x1b[93m{}\x1b[0m", status_code),
-        500..=599 => format!("\x1b[91m{}\x1b[0m", status_code),
-        _ => status_code.to_string(),
+fn gateway_error_status(e: &Error) -> StatusCode {
+    match e {
+        Error::ProxyError(ProxyError::UpstreamTimeout(_)) => {
+            StatusCode::GATEWAY_TIMEOUT
+        }
+        Error::ProxyError(ProxyError::UpstreamRequestFailed(e))
+            if is_timeout(e) =>
+        {
+            StatusCode::GATEWAY_TIMEOUT
+        }
+        _ => StatusCode::BAD_GATEWAY,
     }
 }
 
-pub fn build_request(
-    mut req: Request<Incoming>,
+fn is_timeout(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+
+    while let Some(e) = source {
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            if io_error.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+
+        source = e.source();
+    }
+
+    false
+}
+
+pub fn build_request<B>(
+    mut req: Request<B>,
     host: &str,
     local_port: u16,
     proxy: &ProxyConfig,
-) -> Result<Request<Incoming>, Error> {
+) -> Result<Request<B>, Error> {
     let local_addr = build_addr(host, local_port);
-    let remote_addr = build_addr(host, proxy.remote_port);
+    let remote_addr = build_addr(proxy.remote_host_or(host), proxy.remote_port);
+
+    let upgrade = websocket::websocket_upgrade(&req);
 
     strip_hop_by_hop_headers(req.headers_mut());
     add_x_forwarded_for_header(req.headers_mut(), &local_addr)?;
     add_host_header(req.headers_mut(), &remote_addr)?;
 
-    if let Some(upgrade) = req.headers().get(hyper::header::UPGRADE) {
-        println!("upgrade header: {:#?}", upgrade);
+    if let Some(upgrade) = upgrade {
+        websocket::prepare_upstream_request(&mut req, upgrade);
+    }
+
+    if req.version() == Version::HTTP_2 {
+        *req.version_mut() = Version::HTTP_11;
     }
 
     let mapped_uri = map_proxy_uri(req.uri(), proxy)?;
-    *req.uri_mut() = mapped_uri;
+    *req.uri_mut() =
+        build_upstream_uri(&mapped_uri, proxy.scheme, &remote_addr)?;
 
     Ok(req)
 }
 
 pub async fn send_request(
-    mut client: SendRequest<Incoming>,
-    proxy_request: Request<Incoming>,
-) -> Result<Response<Incoming>, hyper::Error> {
-    let res = client.send_request(proxy_request).await?;
+    client: &HttpClient,
+    proxy_request: Request<ProxyBody>,
+) -> Result<Response<Incoming>, Error> {
+    let res = client.request(proxy_request).await?;
 
     Ok(res)
 }
 
+fn build_upstream_uri(
+    mapped_uri: &Uri,
+    scheme: Scheme,
+    remote_addr: &str,
+) -> Result<Uri, Error> {
+    let path_and_query = mapped_uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    Ok(Uri::builder()
+        .scheme(scheme.as_str())
+        .authority(remote_addr)
+        .path_and_query(path_and_query)
+        .build()?)
+}
+
 fn build_addr(hostname: &str, port: u16) -> String {
     format!("{}:{}", hostname, port)
 }
 
 fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let accepts_trailers =
+        websocket::has_token(headers, hyper::header::TE, "trailers");
+
     headers.remove(hyper::header::CONNECTION);
     headers.remove(HeaderName::from_static("keep-alive"));
     headers.remove(hyper::header::PROXY_AUTHENTICATE);
@@ -197,6 +1067,12 @@ fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
     headers.remove(hyper::header::TRAILER);
     headers.remove(hyper::header::TRANSFER_ENCODING);
     headers.remove(hyper::header::UPGRADE);
+
+    // `te: trailers` is the only TE value allowed over HTTP/2 and is
+    // required by gRPC, so keep it when the client accepts trailers.
+    if accepts_trailers {
+        headers.insert(hyper::header::TE, HeaderValue::from_static("trailers"));
+    }
 }
 
 fn add_x_forwarded_for_header(
@@ -238,17 +1114,35 @@ fn add_host_header(
     Ok(())
 }
 
-fn get_proxy(req_uri: String, proxies: &[ProxyConfig]) -> &ProxyConfig {
-    proxies
-        .iter()
-        .rfind(|x| req_uri.starts_with(&x.local_path))
-        .unwrap()
-}
-
 pub fn map_proxy_uri(req_uri: &Uri, proxy: &ProxyConfig) -> Result<Uri, Error> {
-    Ok(req_uri
-        .to_string()
-        .replace(&proxy.local_path, &proxy.remote_path)
-        .parse::<hyper::Uri>()
-        .unwrap())
+    let rest = router::strip_prefix(&proxy.local_path, req_uri.path())
+        .ok_or_else(|| {
+            Error::ProxyError(ProxyError::PathMismatch(
+                String::from(req_uri.path()),
+                proxy.local_path.clone(),
+            ))
+        })?;
+
+    // A trailing slash that is part of the local path (e.g. the root `/`)
+    // has been matched rather than left over.
+    let rest = match proxy.local_path.ends_with('/') && rest == "/" {
+        true => "",
+        false => rest,
+    };
+
+    let mut path_and_query = match rest {
+        "" => proxy.remote_path.clone(),
+        _ => [proxy.remote_path.trim_end_matches('/'), rest].join(""),
+    };
+
+    if path_and_query.is_empty() {
+        path_and_query.push('/');
+    }
+
+    if let Some(query) = req_uri.query() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+
+    Ok(Uri::builder().path_and_query(path_and_query).build()?)
 }
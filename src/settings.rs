@@ -4,7 +4,7 @@ use clap::Parser;
 use std::ffi::OsString;
 use std::{fmt::Display, fs, path::PathBuf, str::FromStr};
 
-#[derive(Ord, Eq, PartialOrd, Debug, PartialEq)]
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Clone)]
 pub struct Settings {
     pub host: String,
     pub local_port: u16,
@@ -13,6 +13,108 @@ pub struct Settings {
     pub tls: bool,
     pub pem: Option<PathBuf>,
     pub key: Option<PathBuf>,
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: u64,
+    pub connect_timeout: u64,
+    pub response_timeout: u64,
+    pub shutdown_timeout: u64,
+    pub ejection_time: u64,
+    pub fallback: Option<ProxyConfig>,
+    pub dev: bool,
+    pub listeners: Vec<ListenerConfig>,
+    pub redirect_to_https: Option<u16>,
+    pub ca_dir: Option<PathBuf>,
+    pub certificates: Vec<CertificateConfig>,
+    pub access_log: AccessLogConfig,
+    pub admin: Option<AdminConfig>,
+    pub route_edits: Vec<RouteEdit>,
+}
+
+#[derive(
+    Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, serde::Deserialize,
+)]
+pub struct CertificateConfig {
+    pub hosts: Vec<String>,
+    pub pem: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(
+    Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, serde::Deserialize,
+)]
+pub struct AdminConfig {
+    pub host: Option<String>,
+    pub port: u16,
+}
+
+// Only the local host and path of a removed route are used.
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Clone)]
+pub enum RouteEdit {
+    Set(ProxyConfig),
+    Remove(ProxyConfig),
+}
+
+impl RouteEdit {
+    fn route(&self) -> &ProxyConfig {
+        match self {
+            RouteEdit::Set(proxy) | RouteEdit::Remove(proxy) => proxy,
+        }
+    }
+}
+
+#[derive(
+    Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, serde::Deserialize,
+)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(
+    Ord,
+    Eq,
+    PartialOrd,
+    Debug,
+    PartialEq,
+    Default,
+    Clone,
+    Copy,
+    Hash,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    #[default]
+    Pretty,
+    Json,
+    Combined,
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(AccessLogFormat::Pretty),
+            "json" => Ok(AccessLogFormat::Json),
+            "combined" => Ok(AccessLogFormat::Combined),
+            _ => Err(Error::ParseError(ParseError::AccessLogFormat(
+                String::from(s),
+            ))),
+        }
+    }
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone)]
+pub struct ListenerConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    pub tls: bool,
+    pub pem: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub proxies: Option<Vec<ProxyConfig>>,
+    pub redirect_to_https: Option<u16>,
 }
 
 impl Default for Settings {
@@ -25,6 +127,21 @@ impl Default for Settings {
             tls: false,
             pem: None,
             key: None,
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            pool_idle_timeout: default_pool_idle_timeout(),
+            connect_timeout: default_connect_timeout(),
+            response_timeout: default_response_timeout(),
+            shutdown_timeout: default_shutdown_timeout(),
+            ejection_time: default_ejection_time(),
+            fallback: None,
+            dev: false,
+            listeners: vec![],
+            redirect_to_https: None,
+            ca_dir: None,
+            certificates: vec![],
+            access_log: AccessLogConfig::default(),
+            admin: None,
+            route_edits: vec![],
         }
     }
 }
@@ -37,12 +154,11 @@ impl Display for Settings {
             self.proxies
                 .iter()
                 .map(|x| format!(
-                    "\x1b[95mᴥ\x1b[0m {}:{}{} \x1b[94m➡\x1b[0m :{}{}",
-                    self.host,
+                    "ᴥ {}:{}{} ➡ {}",
+                    x.local_host.as_deref().unwrap_or(&self.host),
                     self.local_port,
                     x.local_path,
-                    x.remote_port,
-                    x.remote_path
+                    display_targets(x)
                 ))
                 .collect::<Vec<String>>()
                 .join("\n")
@@ -69,43 +185,500 @@ impl Settings {
             tls: other.tls,
             pem: other.pem.clone(),
             key: other.key.clone(),
+            pool_max_idle_per_host: other.pool_max_idle_per_host,
+            pool_idle_timeout: other.pool_idle_timeout,
+            connect_timeout: other.connect_timeout,
+            response_timeout: other.response_timeout,
+            shutdown_timeout: other.shutdown_timeout,
+            ejection_time: other.ejection_time,
+            fallback: other.fallback.clone(),
+            dev: other.dev,
+            listeners: other.listeners.clone(),
+            redirect_to_https: other.redirect_to_https,
+            ca_dir: other.ca_dir.clone(),
+            certificates: other.certificates.clone(),
+            access_log: other.access_log.clone(),
+            admin: other.admin.clone(),
+            route_edits: other.route_edits.clone(),
         }
     }
+
+    // Route changes made through the admin API are kept, so that they can be
+    // made again on top of the config file when it's reloaded.
+    pub fn edit_route(&mut self, edit: RouteEdit) -> bool {
+        if !self.apply_route_edit(&edit) {
+            return false;
+        }
+
+        self.route_edits
+            .retain(|e| !e.route().same_route(edit.route()));
+        self.route_edits.push(edit);
+
+        true
+    }
+
+    pub fn replay_route_edits(&mut self, edits: &[RouteEdit]) {
+        for edit in edits {
+            self.edit_route(edit.clone());
+        }
+    }
+
+    fn apply_route_edit(&mut self, edit: &RouteEdit) -> bool {
+        let position =
+            self.proxies.iter().position(|p| p.same_route(edit.route()));
+
+        match (edit, position) {
+            (RouteEdit::Set(proxy), Some(i)) => self.proxies[i] = proxy.clone(),
+            (RouteEdit::Set(proxy), None) => self.proxies.push(proxy.clone()),
+            (RouteEdit::Remove(_), Some(i)) => {
+                self.proxies.remove(i);
+            }
+            (RouteEdit::Remove(_), None) => return false,
+        }
+
+        true
+    }
+
+    pub fn listener_settings(&self) -> Vec<Settings> {
+        if self.listeners.is_empty() {
+            return vec![self.clone()];
+        }
+
+        self.listeners
+            .iter()
+            .map(|listener| Settings {
+                host: listener.host.clone().unwrap_or(self.host.clone()),
+                local_port: listener.port,
+                tls: listener.tls,
+                pem: listener.pem.clone(),
+                key: listener.key.clone(),
+                proxies: listener
+                    .proxies
+                    .clone()
+                    .unwrap_or(self.proxies.clone()),
+                redirect_to_https: listener.redirect_to_https,
+                listeners: vec![],
+                ..self.clone()
+            })
+            .collect()
+    }
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, Copy, Hash)]
+pub enum Scheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+        }
+    }
+}
+
+#[derive(
+    Ord,
+    Eq,
+    PartialOrd,
+    Debug,
+    PartialEq,
+    Default,
+    Clone,
+    Copy,
+    Hash,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamProtocol {
+    #[default]
+    Http1,
+    H2c,
+    H2,
 }
 
-#[derive(Ord, Eq, PartialOrd, Debug, PartialEq)]
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, Hash)]
+pub struct UpstreamTls {
+    pub ca: Option<PathBuf>,
+    pub sni: Option<String>,
+    pub insecure: bool,
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, Hash)]
+pub struct UpstreamTarget {
+    pub host: Option<String>,
+    pub port: u16,
+}
+
+impl FromStr for UpstreamTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = match s.rsplit_once(':') {
+            Some(("", port)) => (None, port),
+            Some((host, port)) => (Some(String::from(host)), port),
+            None => (None, s),
+        };
+
+        let port = port.parse::<u16>()?;
+
+        if let Some(host) = &host {
+            validate_authority(host, port)?;
+        }
+
+        Ok(UpstreamTarget { host, port })
+    }
+}
+
+#[derive(
+    Ord, Eq, PartialOrd, Debug, PartialEq, Clone, Hash, serde::Deserialize,
+)]
+pub struct HealthCheckConfig {
+    pub path: String,
+
+    #[serde(default = "default_health_check_interval")]
+    pub interval: u64,
+
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout: u64,
+
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+}
+
+#[derive(
+    Ord, Eq, PartialOrd, Debug, PartialEq, Clone, Hash, serde::Deserialize,
+)]
+pub struct AffinityConfig {
+    #[serde(default = "default_affinity_cookie")]
+    pub cookie: String,
+
+    // Signs the cookie, so that it stays valid across restarts. Otherwise a
+    // random key is used for the life of the process.
+    pub secret: Option<String>,
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Clone, Hash)]
+pub enum HashKey {
+    Header(String),
+    Cookie(String),
+    ClientIp,
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone, Hash)]
+pub enum LoadBalancing {
+    #[default]
+    Failover,
+    RoundRobin,
+    LeastConnections,
+    Random,
+    Hash(HashKey),
+}
+
+impl FromStr for LoadBalancing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let strategy = match s.split_once(':') {
+            None => match s {
+                "failover" => Some(LoadBalancing::Failover),
+                "round_robin" => Some(LoadBalancing::RoundRobin),
+                "least_connections" => Some(LoadBalancing::LeastConnections),
+                "random" => Some(LoadBalancing::Random),
+                _ => None,
+            },
+            Some(("hash", key)) => match key.split_once(':') {
+                Some(("header", name)) if !name.is_empty() => Some(
+                    LoadBalancing::Hash(HashKey::Header(name.to_lowercase())),
+                ),
+                Some(("cookie", name)) if !name.is_empty() => Some(
+                    LoadBalancing::Hash(HashKey::Cookie(String::from(name))),
+                ),
+                None if key == "client_ip" => {
+                    Some(LoadBalancing::Hash(HashKey::ClientIp))
+                }
+                _ => None,
+            },
+            Some(_) => None,
+        };
+
+        strategy.ok_or_else(|| {
+            Error::ParseError(ParseError::LoadBalancing(String::from(s)))
+        })
+    }
+}
+
+impl Display for LoadBalancing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadBalancing::Failover => write!(f, "failover"),
+            LoadBalancing::RoundRobin => write!(f, "round_robin"),
+            LoadBalancing::LeastConnections => write!(f, "least_connections"),
+            LoadBalancing::Random => write!(f, "random"),
+            LoadBalancing::Hash(HashKey::Header(name)) => {
+                write!(f, "hash:header:{}", name)
+            }
+            LoadBalancing::Hash(HashKey::Cookie(name)) => {
+                write!(f, "hash:cookie:{}", name)
+            }
+            LoadBalancing::Hash(HashKey::ClientIp) => {
+                write!(f, "hash:client_ip")
+            }
+        }
+    }
+}
+
+#[derive(Ord, Eq, PartialOrd, Debug, PartialEq, Default, Clone)]
 pub struct ProxyConfig {
+    pub local_host: Option<String>,
     pub local_path: String,
+    pub scheme: Scheme,
+    pub remote_host: Option<String>,
     pub remote_port: u16,
     pub remote_path: String,
+    pub protocol: UpstreamProtocol,
+    pub tls: UpstreamTls,
+    pub targets: Vec<UpstreamTarget>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub load_balancing: LoadBalancing,
+    pub affinity: Option<AffinityConfig>,
+}
+
+impl ProxyConfig {
+    // Routes are identified by what they match on, i.e. local host and path.
+    pub fn same_route(&self, other: &ProxyConfig) -> bool {
+        self.local_host == other.local_host
+            && self.local_path == other.local_path
+    }
+
+    pub fn remote_host_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.remote_host.as_deref().unwrap_or(default)
+    }
+
+    // The remote host and port followed by any additional targets, each as a
+    // proxy config of its own, in the order they should be tried.
+    pub fn upstream_targets(&self) -> Vec<ProxyConfig> {
+        let primary = ProxyConfig {
+            targets: vec![],
+            ..self.clone()
+        };
+
+        let mut upstream_targets = vec![primary.clone()];
+
+        upstream_targets.extend(self.targets.iter().map(|target| {
+            ProxyConfig {
+                remote_host: target.host.clone(),
+                remote_port: target.port,
+                ..primary.clone()
+            }
+        }));
+
+        upstream_targets
+    }
+
+    pub fn upstream(&self, default_host: &str) -> String {
+        format!(
+            "{}://{}:{}",
+            self.scheme.as_str(),
+            self.remote_host_or(default_host),
+            self.remote_port
+        )
+    }
+
+    pub fn remote_authority(&self) -> String {
+        let authority = format!(
+            "{}:{}",
+            self.remote_host.as_deref().unwrap_or(""),
+            self.remote_port
+        );
+
+        match (self.scheme, self.protocol) {
+            (Scheme::Http, UpstreamProtocol::H2c) => {
+                format!("h2c://{}", authority)
+            }
+            (Scheme::Https, UpstreamProtocol::H2) => {
+                format!("h2://{}", authority)
+            }
+            (Scheme::Https, _) => format!("https://{}", authority),
+            (Scheme::Http, _) => authority,
+        }
+    }
+}
+
+impl Display for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{} -> {}{}",
+            self.local_host.as_deref().unwrap_or(""),
+            self.local_path,
+            self.remote_authority(),
+            self.remote_path
+        )
+    }
+}
+
+pub(crate) fn display_targets(proxy: &ProxyConfig) -> String {
+    let targets = proxy
+        .upstream_targets()
+        .iter()
+        .map(|t| format!("{}{}", t.remote_authority(), t.remote_path))
+        .collect::<Vec<String>>();
+
+    let sticky = match proxy.affinity {
+        Some(_) => ", sticky",
+        None => "",
+    };
+
+    match targets.len() {
+        1 => targets.join(""),
+        _ => format!(
+            "{} ({}{})",
+            targets.join(", "),
+            proxy.load_balancing,
+            sticky
+        ),
+    }
 }
 
 impl FromStr for ProxyConfig {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some((local_path, remote)) = s.split_once(':') {
-            let (remote_port, remote_path) = if let Some((
-                remote_port,
-                remote_path,
-            )) = remote.split_once('/')
-            {
-                (remote_port, remote_path)
+        if let Some((local, remote)) = s.split_once(':') {
+            let mut proxy = if remote.contains("://") {
+                parse_remote_url(remote)?
             } else {
-                (remote, "")
+                parse_remote(remote)?
             };
 
-            Ok(ProxyConfig {
-                local_path: ["/", local_path].join(""),
-                remote_port: remote_port.parse::<u16>()?,
-                remote_path: ["/", remote_path].join(""),
-            })
+            let (local_host, local_path) = match local.strip_prefix("//") {
+                Some(local) => {
+                    let (local_host, local_path) =
+                        local.split_once('/').unwrap_or((local, ""));
+
+                    (Some(parse_local_host(local_host)?), local_path)
+                }
+                None => (None, local),
+            };
+
+            proxy.local_host = local_host;
+            proxy.local_path = ["/", local_path].join("");
+
+            Ok(proxy)
         } else {
             Err(Error::ParseError(ParseError::ProxyDefinition))
         }
     }
 }
 
+fn parse_local_host(local_host: &str) -> Result<String, Error> {
+    let name = local_host.strip_prefix("*.").unwrap_or(local_host);
+
+    if name.is_empty()
+        || name.contains(['*', ':', '/'])
+        || name.starts_with('.')
+        || name.ends_with('.')
+    {
+        return Err(Error::ParseError(ParseError::ProxyDefinition));
+    }
+
+    Ok(local_host.to_ascii_lowercase())
+}
+
+fn parse_remote(remote: &str) -> Result<ProxyConfig, Error> {
+    let (authority, remote_path) =
+        if let Some((authority, remote_path)) = remote.split_once('/') {
+            (authority, remote_path)
+        } else {
+            (remote, "")
+        };
+
+    let (remote_host, remote_port) =
+        if let Some((remote_host, remote_port)) = authority.rsplit_once(':') {
+            if remote_host.is_empty() {
+                return Err(Error::ParseError(ParseError::ProxyDefinition));
+            }
+
+            (Some(String::from(remote_host)), remote_port)
+        } else {
+            (None, authority)
+        };
+
+    let remote_port = remote_port.parse::<u16>()?;
+
+    if let Some(remote_host) = &remote_host {
+        validate_authority(remote_host, remote_port)?;
+    }
+
+    Ok(ProxyConfig {
+        remote_host,
+        remote_port,
+        remote_path: ["/", remote_path].join(""),
+        ..ProxyConfig::default()
+    })
+}
+
+// The fallback handles requests that didn't match a proxy, so there's no
+// local path to strip from them.
+pub fn validate_fallback(proxy: &ProxyConfig) -> Result<(), Error> {
+    match proxy.local_path.as_str() {
+        "/" => Ok(()),
+        path => Err(Error::ParseError(ParseError::FallbackLocalPath(
+            String::from(path),
+        ))),
+    }
+}
+
+// Catches a bad remote host when the settings are loaded, rather than on
+// every request to it.
+fn validate_authority(host: &str, port: u16) -> Result<(), Error> {
+    format!("{}:{}", host, port)
+        .parse::<hyper::http::uri::Authority>()
+        .map_err(|_| Error::ParseError(ParseError::ProxyDefinition))?;
+
+    Ok(())
+}
+
+fn parse_remote_url(remote: &str) -> Result<ProxyConfig, Error> {
+    let uri = remote
+        .parse::<hyper::Uri>()
+        .map_err(|_| Error::ParseError(ParseError::ProxyDefinition))?;
+
+    let (scheme, protocol) = match uri.scheme_str() {
+        Some("http") => (Scheme::Http, UpstreamProtocol::Http1),
+        Some("https") => (Scheme::Https, UpstreamProtocol::Http1),
+        Some("h2c") => (Scheme::Http, UpstreamProtocol::H2c),
+        Some("h2") => (Scheme::Https, UpstreamProtocol::H2),
+        _ => return Err(Error::ParseError(ParseError::ProxyDefinition)),
+    };
+
+    let remote_host = uri
+        .host()
+        .filter(|h| !h.is_empty())
+        .ok_or(Error::ParseError(ParseError::ProxyDefinition))?;
+
+    let default_port = match scheme {
+        Scheme::Http => 80,
+        Scheme::Https => 443,
+    };
+
+    Ok(ProxyConfig {
+        scheme,
+        protocol,
+        remote_host: Some(String::from(remote_host)),
+        remote_port: uri.port_u16().unwrap_or(default_port),
+        remote_path: String::from(uri.path()),
+        ..ProxyConfig::default()
+    })
+}
+
 impl TryFrom<Cli> for Settings {
     type Error = Error;
 
@@ -116,6 +689,26 @@ impl TryFrom<Cli> for Settings {
             .map(|p| ProxyConfig::from_str(p))
             .collect::<Result<Vec<ProxyConfig>, Error>>()?;
 
+        let fallback = value
+            .fallback
+            .as_deref()
+            .map(ProxyConfig::from_str)
+            .transpose()?;
+
+        if let Some(fallback) = &fallback {
+            validate_fallback(fallback)?;
+        }
+
+        let access_log = AccessLogConfig {
+            format: value
+                .access_log_format
+                .as_deref()
+                .map(AccessLogFormat::from_str)
+                .transpose()?
+                .unwrap_or_default(),
+            path: value.access_log,
+        };
+
         Ok(Settings {
             host: value.host,
             local_port: value.local_port,
@@ -124,6 +717,14 @@ impl TryFrom<Cli> for Settings {
             tls: value.tls,
             pem: value.pem,
             key: value.key,
+            fallback,
+            dev: value.dev,
+            ca_dir: value.ca_dir,
+            access_log,
+            admin: value
+                .admin_port
+                .map(|port| AdminConfig { host: None, port }),
+            ..Settings::default()
         })
     }
 }
@@ -136,6 +737,120 @@ fn default_port() -> u16 {
     80
 }
 
+fn default_pool_max_idle_per_host() -> usize {
+    32
+}
+
+fn default_pool_idle_timeout() -> u64 {
+    90
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_response_timeout() -> u64 {
+    60
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_ejection_time() -> u64 {
+    10
+}
+
+fn default_health_check_interval() -> u64 {
+    10
+}
+
+fn default_health_check_timeout() -> u64 {
+    2
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_affinity_cookie() -> String {
+    String::from("joubini_affinity")
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum ConfigFileProxy {
+    Definition(String),
+    Detailed(Box<ConfigFileProxyDetails>),
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ConfigFileProxyDetails {
+    proxy: String,
+    host: Option<String>,
+    protocol: Option<UpstreamProtocol>,
+    ca: Option<PathBuf>,
+    sni: Option<String>,
+
+    #[serde(default)]
+    insecure: bool,
+
+    #[serde(default)]
+    targets: Vec<String>,
+
+    health_check: Option<HealthCheckConfig>,
+    load_balancing: Option<String>,
+    affinity: Option<AffinityConfig>,
+}
+
+impl TryFrom<&ConfigFileProxy> for ProxyConfig {
+    type Error = Error;
+
+    fn try_from(value: &ConfigFileProxy) -> Result<Self, Self::Error> {
+        match value {
+            ConfigFileProxy::Definition(proxy) => ProxyConfig::from_str(proxy),
+            ConfigFileProxy::Detailed(details) => {
+                let mut proxy = ProxyConfig::from_str(&details.proxy)?;
+
+                if let Some(host) = &details.host {
+                    proxy.local_host = Some(parse_local_host(host)?);
+                }
+
+                if let Some(protocol) = details.protocol {
+                    proxy.protocol = protocol;
+                }
+
+                proxy.tls = UpstreamTls {
+                    ca: details.ca.clone(),
+                    sni: details.sni.clone(),
+                    insecure: details.insecure,
+                };
+
+                proxy.targets = details
+                    .targets
+                    .iter()
+                    .map(|t| UpstreamTarget::from_str(t))
+                    .collect::<Result<Vec<UpstreamTarget>, Error>>()?;
+
+                proxy.health_check = details.health_check.clone();
+
+                proxy.affinity = details.affinity.clone();
+
+                if let Some(load_balancing) = &details.load_balancing {
+                    proxy.load_balancing =
+                        LoadBalancing::from_str(load_balancing)?;
+                }
+
+                Ok(proxy)
+            }
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ConfigFileProxies {
     #[serde(default = "default_host")]
@@ -144,11 +859,88 @@ struct ConfigFileProxies {
     #[serde(rename = "port", default = "default_port")]
     local_port: u16,
 
-    proxies: Vec<String>,
+    proxies: Vec<ConfigFileProxy>,
 
     tls: Option<bool>,
     pem: Option<PathBuf>,
     key: Option<PathBuf>,
+
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pool_max_idle_per_host: usize,
+
+    #[serde(default = "default_pool_idle_timeout")]
+    pool_idle_timeout: u64,
+
+    #[serde(default = "default_connect_timeout")]
+    connect_timeout: u64,
+
+    #[serde(default = "default_response_timeout")]
+    response_timeout: u64,
+
+    #[serde(default = "default_shutdown_timeout")]
+    shutdown_timeout: u64,
+
+    #[serde(default = "default_ejection_time")]
+    ejection_time: u64,
+
+    fallback: Option<ConfigFileProxy>,
+
+    #[serde(default)]
+    dev: bool,
+
+    #[serde(default)]
+    listeners: Vec<ConfigFileListener>,
+
+    ca_dir: Option<PathBuf>,
+
+    #[serde(default)]
+    certificates: Vec<CertificateConfig>,
+
+    #[serde(default)]
+    access_log: AccessLogConfig,
+
+    admin: Option<AdminConfig>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ConfigFileListener {
+    host: Option<String>,
+    port: u16,
+
+    #[serde(default)]
+    tls: bool,
+
+    pem: Option<PathBuf>,
+    key: Option<PathBuf>,
+    proxies: Option<Vec<ConfigFileProxy>>,
+    redirect_to_https: Option<u16>,
+}
+
+impl TryFrom<&ConfigFileListener> for ListenerConfig {
+    type Error = Error;
+
+    fn try_from(value: &ConfigFileListener) -> Result<Self, Self::Error> {
+        let proxies = value
+            .proxies
+            .as_ref()
+            .map(|proxies| {
+                proxies
+                    .iter()
+                    .map(ProxyConfig::try_from)
+                    .collect::<Result<Vec<ProxyConfig>, Error>>()
+            })
+            .transpose()?;
+
+        Ok(ListenerConfig {
+            host: value.host.clone(),
+            port: value.port,
+            tls: value.tls,
+            pem: value.pem.clone(),
+            key: value.key.clone(),
+            proxies,
+            redirect_to_https: value.redirect_to_https,
+        })
+    }
 }
 
 impl TryFrom<PathBuf> for Settings {
@@ -161,9 +953,25 @@ impl TryFrom<PathBuf> for Settings {
         let proxies = config_yaml
             .proxies
             .iter()
-            .map(|p| ProxyConfig::from_str(p))
+            .map(ProxyConfig::try_from)
             .collect::<Result<Vec<ProxyConfig>, Error>>()?;
 
+        let fallback = config_yaml
+            .fallback
+            .as_ref()
+            .map(ProxyConfig::try_from)
+            .transpose()?;
+
+        if let Some(fallback) = &fallback {
+            validate_fallback(fallback)?;
+        }
+
+        let listeners = config_yaml
+            .listeners
+            .iter()
+            .map(ListenerConfig::try_from)
+            .collect::<Result<Vec<ListenerConfig>, Error>>()?;
+
         let tls = config_yaml.tls.is_some();
 
         Ok(Settings {
@@ -174,6 +982,21 @@ impl TryFrom<PathBuf> for Settings {
             tls,
             pem: config_yaml.pem,
             key: config_yaml.key,
+            pool_max_idle_per_host: config_yaml.pool_max_idle_per_host,
+            pool_idle_timeout: config_yaml.pool_idle_timeout,
+            connect_timeout: config_yaml.connect_timeout,
+            response_timeout: config_yaml.response_timeout,
+            shutdown_timeout: config_yaml.shutdown_timeout,
+            ejection_time: config_yaml.ejection_time,
+            fallback,
+            dev: config_yaml.dev,
+            listeners,
+            redirect_to_https: None,
+            ca_dir: config_yaml.ca_dir,
+            certificates: config_yaml.certificates,
+            access_log: config_yaml.access_log,
+            admin: config_yaml.admin,
+            route_edits: vec![],
         })
     }
 }
@@ -0,0 +1,111 @@
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::sync::watch;
+use tracing::Instrument;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Running,
+    Draining,
+    Terminated,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    phase: Arc<watch::Sender<Phase>>,
+    active: Arc<watch::Sender<usize>>,
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        ShutdownHandle {
+            phase: Arc::new(watch::channel(Phase::Running).0),
+            active: Arc::new(watch::channel(0).0),
+        }
+    }
+}
+
+impl ShutdownHandle {
+    pub fn new() -> ShutdownHandle {
+        ShutdownHandle::default()
+    }
+
+    pub fn shutdown(&self) {
+        self.phase.send_if_modified(|phase| match phase {
+            Phase::Running => {
+                *phase = Phase::Draining;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.phase.borrow() != Phase::Running
+    }
+
+    pub fn active_connections(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    pub async fn requested(&self) {
+        self.wait_for(Phase::Draining).await
+    }
+
+    pub(crate) async fn terminated(&self) {
+        self.wait_for(Phase::Terminated).await
+    }
+
+    // Runs a connection (or anything holding one open, like a web socket
+    // tunnel) so that it's counted while draining and dropped once the
+    // drain deadline has passed.
+    pub(crate) fn spawn(
+        &self,
+        task: impl Future<Output = ()> + Send + 'static,
+    ) {
+        self.active.send_modify(|active| *active += 1);
+
+        let shutdown = self.clone();
+        let guard = ActiveGuard(self.active.clone());
+
+        let task = task.in_current_span();
+
+        tokio::task::spawn(async move {
+            let _guard = guard;
+
+            tokio::select! {
+                _ = task => {}
+                _ = shutdown.terminated() => {}
+            }
+        });
+    }
+
+    pub(crate) async fn drain(&self, deadline: Duration) -> bool {
+        let mut active = self.active.subscribe();
+
+        let drained = tokio::time::timeout(
+            deadline,
+            active.wait_for(|active| *active == 0),
+        )
+        .await
+        .is_ok();
+
+        self.phase.send_replace(Phase::Terminated);
+
+        drained
+    }
+
+    async fn wait_for(&self, phase: Phase) {
+        let mut rx = self.phase.subscribe();
+
+        // The sender lives as long as `self`, so this can't fail.
+        let _ = rx.wait_for(|current| *current >= phase).await;
+    }
+}
+
+struct ActiveGuard(Arc<watch::Sender<usize>>);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|active| *active -= 1);
+    }
+}
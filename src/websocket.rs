@@ -0,0 +1,130 @@
+use crate::{balancer::ActiveRequest, metrics, shutdown::ShutdownHandle};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hyper::{
+    body::{Bytes, Incoming},
+    ext::Protocol,
+    header::{self, HeaderValue},
+    upgrade::OnUpgrade,
+    HeaderMap, Method, Request, Response, StatusCode, Version,
+};
+use hyper_util::rt::TokioIo;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebSocketUpgrade {
+    Http1,
+    ExtendedConnect,
+}
+
+pub fn websocket_upgrade<B>(req: &Request<B>) -> Option<WebSocketUpgrade> {
+    if req.method() == Method::CONNECT {
+        return req
+            .extensions()
+            .get::<Protocol>()
+            .filter(|p| p.as_str().eq_ignore_ascii_case("websocket"))
+            .map(|_| WebSocketUpgrade::ExtendedConnect);
+    }
+
+    if req.version() <= Version::HTTP_11
+        && has_token(req.headers(), header::CONNECTION, "upgrade")
+        && has_token(req.headers(), header::UPGRADE, "websocket")
+    {
+        return Some(WebSocketUpgrade::Http1);
+    }
+
+    None
+}
+
+pub fn has_token(
+    headers: &HeaderMap,
+    name: header::HeaderName,
+    token: &str,
+) -> bool {
+    headers.get_all(name).iter().any(|v| {
+        v.to_str().is_ok_and(|v| {
+            v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token))
+        })
+    })
+}
+
+pub fn prepare_upstream_request<B>(
+    req: &mut Request<B>,
+    upgrade: WebSocketUpgrade,
+) {
+    if upgrade == WebSocketUpgrade::ExtendedConnect {
+        *req.method_mut() = Method::GET;
+        *req.version_mut() = Version::HTTP_11;
+
+        let key: [u8; 16] = rand::random();
+        req.headers_mut().insert(
+            header::SEC_WEBSOCKET_KEY,
+            HeaderValue::from_str(&STANDARD.encode(key))
+                .expect("Base64 should be a valid header value."),
+        );
+    }
+
+    req.headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    req.headers_mut()
+        .insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+}
+
+pub fn tunnel(
+    upgrade: WebSocketUpgrade,
+    downstream: OnUpgrade,
+    mut res: Response<Incoming>,
+    active: ActiveRequest,
+    shutdown: &ShutdownHandle,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let upstream = hyper::upgrade::on(&mut res);
+
+    shutdown.spawn(async move {
+        // The tunnel counts as a request to the upstream until it's closed.
+        let _active_request = active;
+
+        match tokio::try_join!(downstream, upstream) {
+            Ok((downstream, upstream)) => {
+                let _active = metrics::websocket_opened();
+
+                let mut downstream = TokioIo::new(downstream);
+                let mut upstream = TokioIo::new(upstream);
+
+                if let Err(e) = tokio::io::copy_bidirectional(
+                    &mut downstream,
+                    &mut upstream,
+                )
+                .await
+                {
+                    tracing::warn!(error = %e, "Web socket tunnel closed");
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Unable to upgrade connection");
+            }
+        }
+    });
+
+    match upgrade {
+        WebSocketUpgrade::Http1 => res.map(|b| b.boxed()),
+        WebSocketUpgrade::ExtendedConnect => {
+            let mut connect_res = Response::new(
+                Empty::<Bytes>::new()
+                    .map_err(|never| match never {})
+                    .boxed(),
+            );
+
+            *connect_res.status_mut() = StatusCode::OK;
+
+            for name in [
+                header::SEC_WEBSOCKET_PROTOCOL,
+                header::SEC_WEBSOCKET_EXTENSIONS,
+            ] {
+                if let Some(value) = res.headers().get(&name) {
+                    connect_res.headers_mut().insert(name, value.clone());
+                }
+            }
+
+            connect_res
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{error::Error, str::FromStr};
+
+use hyper::{header, HeaderMap};
+use joubini::{
+    affinity::{pinned, set_cookie, sign, verify},
+    settings::{AffinityConfig, ProxyConfig},
+};
+
+fn sticky_proxy(definition: &str, secret: Option<&str>) -> ProxyConfig {
+    ProxyConfig {
+        affinity: Some(AffinityConfig {
+            cookie: String::from("affinity"),
+            secret: secret.map(String::from),
+        }),
+        ..ProxyConfig::from_str(definition).unwrap()
+    }
+}
+
+#[test]
+fn test_sign_and_verify_affinity() -> Result<(), Box<dyn Error>> {
+    let proxy = sticky_proxy("api:3000", Some("s3cret"));
+    let config = proxy.affinity.clone().unwrap();
+
+    let value = sign(&config, &proxy, "http://127.0.0.1:3001");
+
+    assert_eq!(
+        verify(&config, &proxy, &value),
+        Some(String::from("http://127.0.0.1:3001"))
+    );
+
+    // Signatures are stable for a given secret, so survive restarts.
+    assert_eq!(value, sign(&config, &proxy, "http://127.0.0.1:3001"));
+
+    let (_, tag) = value.split_once('.').unwrap();
+    let forged = format!("aHR0cDovLzEyNy4wLjAuMTozMDAy.{}", tag);
+
+    assert_eq!(verify(&config, &proxy, &forged), None);
+    assert_eq!(verify(&config, &proxy, "garbage"), None);
+
+    let other_route = sticky_proxy("admin:3000", Some("s3cret"));
+    assert_eq!(verify(&config, &other_route, &value), None);
+
+    let other_secret = sticky_proxy("api:3000", Some("other"));
+    let other_config = other_secret.affinity.clone().unwrap();
+    assert_eq!(verify(&other_config, &other_secret, &value), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_affinity_cookie() -> Result<(), Box<dyn Error>> {
+    let proxy = sticky_proxy("api:3000", None);
+
+    let cookie = set_cookie(&proxy, "http://127.0.0.1:3001").unwrap();
+    let cookie = cookie.to_str()?;
+
+    assert!(cookie.starts_with("affinity="));
+    assert!(cookie.ends_with("; Path=/api; HttpOnly; SameSite=Lax"));
+
+    let value = cookie.split(';').next().unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, format!("a=b; {}; c=d", value).parse()?);
+
+    assert_eq!(
+        pinned(&proxy, &headers),
+        Some(String::from("http://127.0.0.1:3001"))
+    );
+
+    let not_sticky = ProxyConfig::from_str("api:3000")?;
+
+    assert_eq!(pinned(&not_sticky, &headers), None);
+    assert_eq!(set_cookie(&not_sticky, "http://127.0.0.1:3001"), None);
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::{error::Error, net::SocketAddr, str::FromStr};
+
+use hyper::Request;
+use joubini::{
+    balancer::Balancer,
+    health::Health,
+    settings::{LoadBalancing, ProxyConfig, Settings, UpstreamTarget},
+};
+
+#[test]
+fn test_balancer_is_kept_per_server() -> Result<(), Box<dyn Error>> {
+    let proxy = ProxyConfig {
+        targets: vec![UpstreamTarget::from_str(":3999")?],
+        load_balancing: LoadBalancing::RoundRobin,
+        ..ProxyConfig::from_str("api:3998")?
+    };
+
+    let settings = Settings {
+        host: String::from("127.0.0.1"),
+        ..Settings::default()
+    };
+
+    let targets = Health::new().available_targets(&proxy, &settings);
+    let req = Request::new(());
+    let client_addr = SocketAddr::from(([127, 0, 0, 1], 40000));
+
+    let first = |balancer: &Balancer| {
+        balancer.order(
+            &proxy,
+            targets.clone(),
+            &settings.host,
+            &req,
+            client_addr,
+        )[0]
+        .upstream(&settings.host)
+    };
+
+    let balancer = Balancer::new();
+    let other = Balancer::new();
+
+    assert_eq!(first(&balancer), "http://127.0.0.1:3998");
+    assert_eq!(first(&balancer), "http://127.0.0.1:3999");
+
+    // Another server starts its own rotation from the beginning.
+    assert_eq!(first(&other), "http://127.0.0.1:3998");
+
+    let _active = balancer.request_started("http://127.0.0.1:3998");
+
+    assert_eq!(balancer.active_requests("http://127.0.0.1:3998"), 1);
+    assert_eq!(other.active_requests("http://127.0.0.1:3998"), 0);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use joubini::ca::LocalCa;
+
+fn ca_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "joubini-{}-{}",
+        name,
+        std::process::id()
+    ));
+
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
+
+#[test]
+fn test_create_and_reload_ca() -> Result<(), Box<dyn Error>> {
+    let dir = ca_dir("reload-ca");
+
+    let ca = LocalCa::load_or_create(Some(&dir))?;
+
+    assert!(ca.root_pem().starts_with("-----BEGIN CERTIFICATE-----"));
+    assert_eq!(fs::read_to_string(dir.join("ca.crt"))?, ca.root_pem());
+    assert!(dir.join("ca.key").exists());
+
+    let reloaded = LocalCa::load_or_create(Some(&dir))?;
+    assert_eq!(reloaded.root_pem(), ca.root_pem());
+
+    fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_issue_and_cache_leaf_certificates() -> Result<(), Box<dyn Error>> {
+    let dir = ca_dir("issue-leaf");
+
+    let ca = LocalCa::load_or_create(Some(&dir))?;
+
+    let key = ca.certified_key("App.Localhost")?;
+    assert_eq!(key.cert.len(), 2);
+
+    let cert_path = dir.join("certs").join("app.localhost.crt");
+    let cached = fs::read_to_string(&cert_path)?;
+
+    assert!(dir.join("certs").join("app.localhost.key").exists());
+    assert_eq!(ca.certified_key("app.localhost")?.cert, key.cert);
+
+    let reloaded = LocalCa::load_or_create(Some(&dir))?;
+    assert_eq!(reloaded.certified_key("app.localhost")?.cert, key.cert);
+    assert_eq!(fs::read_to_string(&cert_path)?, cached);
+
+    assert!(ca.certified_key("../escape").is_err());
+
+    fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_issue_leaf_certificates_concurrently() -> Result<(), Box<dyn Error>> {
+    let dir = ca_dir("issue-concurrently");
+
+    let ca = LocalCa::load_or_create(Some(&dir))?;
+    let hosts = ["a.localhost", "b.localhost", "c.localhost", "d.localhost"];
+
+    let keys = std::thread::scope(|scope| {
+        let handles = hosts
+            .iter()
+            .map(|host| scope.spawn(|| ca.certified_key(host)))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    for (host, key) in hosts.iter().zip(keys) {
+        assert!(dir.join("certs").join(format!("{}.crt", host)).exists());
+        assert_eq!(ca.certified_key(host)?.cert, key.cert);
+    }
+
+    fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
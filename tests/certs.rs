@@ -0,0 +1,120 @@
+use std::{error::Error, fs, path::Path};
+
+use joubini::{
+    ca::LocalCa,
+    certs::{load_certified_key, CertStore},
+    error::{Error as JoubiniError, TlsError},
+    settings::CertificateConfig,
+};
+
+fn issue(dir: &Path, host: &str) -> Result<CertificateConfig, Box<dyn Error>> {
+    LocalCa::load_or_create(Some(dir))?.certified_key(host)?;
+
+    Ok(CertificateConfig {
+        hosts: vec![String::from(host)],
+        pem: dir.join("certs").join(format!("{}.crt", host)),
+        key: dir.join("certs").join(format!("{}.key", host)),
+    })
+}
+
+#[test]
+fn test_find_certificate_by_host() -> Result<(), Box<dyn Error>> {
+    let dir = std::env::temp_dir()
+        .join(format!("joubini-cert-store-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let app = issue(&dir, "app.localhost")?;
+    let wildcard = issue(&dir, "*.api.localhost")?;
+    let default = issue(&dir, "localhost")?;
+
+    let store = CertStore::load(
+        &[app.clone(), wildcard.clone()],
+        Some((&default.pem, &default.key)),
+    )?;
+
+    let cert = |host| store.find(host).map(|k| k.cert[0].clone());
+    let issued = |c: &CertificateConfig| {
+        rustls_pemfile::certs(&mut fs::read(&c.pem).unwrap().as_slice())
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+
+    assert_eq!(cert(Some("app.localhost")), Some(issued(&app)));
+    assert_eq!(cert(Some("App.Localhost")), Some(issued(&app)));
+    assert_eq!(cert(Some("v1.api.localhost")), Some(issued(&wildcard)));
+    assert_eq!(cert(Some("a.v1.api.localhost")), Some(issued(&default)));
+    assert_eq!(cert(Some("other.localhost")), Some(issued(&default)));
+    assert_eq!(cert(None), Some(issued(&default)));
+
+    let without_default = CertStore::load(&[app], None)?;
+    assert!(without_default.find(Some("other.localhost")).is_none());
+
+    assert!(CertStore::load(
+        &[CertificateConfig {
+            hosts: vec![String::from("missing.localhost")],
+            pem: dir.join("missing.crt"),
+            key: dir.join("missing.key"),
+        }],
+        None
+    )
+    .is_err());
+
+    fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_load_private_key_formats() -> Result<(), Box<dyn Error>> {
+    let fixtures = Path::new("tests/fixtures/tls");
+
+    for name in ["rsa-pkcs1", "ec-sec1", "chain-pkcs8"] {
+        load_certified_key(
+            &fixtures.join(format!("{}.crt", name)),
+            &fixtures.join(format!("{}.key", name)),
+        )?;
+    }
+
+    let chain = load_certified_key(
+        &fixtures.join("chain-pkcs8.crt"),
+        &fixtures.join("chain-pkcs8.key"),
+    )?;
+    assert_eq!(chain.cert.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_key_material() -> Result<(), Box<dyn Error>> {
+    let fixtures = Path::new("tests/fixtures/tls");
+
+    let invalid_key = load_certified_key(
+        &fixtures.join("rsa-pkcs1.crt"),
+        &fixtures.join("invalid.key"),
+    );
+    assert!(matches!(
+        invalid_key,
+        Err(JoubiniError::TlsError(TlsError::InvalidPrivateKey(_)))
+    ));
+
+    let invalid_cert = load_certified_key(
+        &fixtures.join("invalid.key"),
+        &fixtures.join("rsa-pkcs1.key"),
+    );
+    assert!(matches!(
+        invalid_cert,
+        Err(JoubiniError::TlsError(TlsError::InvalidCertificate(_)))
+    ));
+
+    let missing = load_certified_key(
+        &fixtures.join("missing.crt"),
+        &fixtures.join("missing.key"),
+    );
+    assert!(matches!(
+        missing,
+        Err(JoubiniError::TlsError(TlsError::ReadFile(_, _)))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::{error::Error, str::FromStr, time::Duration};
+
+use joubini::{
+    health::{Health, HealthStatus},
+    settings::{ProxyConfig, Settings, UpstreamTarget},
+};
+
+#[test]
+fn test_failing_target_is_ejected_again() -> Result<(), Box<dyn Error>> {
+    let proxy = ProxyConfig {
+        targets: vec![UpstreamTarget::from_str(":3999")?],
+        ..ProxyConfig::from_str("api:3998")?
+    };
+
+    let settings = Settings {
+        host: String::from("127.0.0.1"),
+        ejection_time: 1,
+        ..Settings::default()
+    };
+
+    let health = Health::new();
+    let primary = "http://127.0.0.1:3998";
+    let upstreams = |targets: Vec<ProxyConfig>| {
+        targets
+            .iter()
+            .map(|t| t.upstream(&settings.host))
+            .collect::<Vec<String>>()
+    };
+
+    health.record_failure(primary, "Connection refused", true);
+
+    assert_eq!(health.get(primary).status, HealthStatus::Unhealthy);
+    assert_eq!(
+        upstreams(health.available_targets(&proxy, &settings)),
+        vec!["http://127.0.0.1:3999"]
+    );
+
+    // Once the ejection time has passed the target gets another chance...
+    std::thread::sleep(Duration::from_millis(1100));
+
+    assert_eq!(
+        upstreams(health.available_targets(&proxy, &settings)),
+        vec!["http://127.0.0.1:3998", "http://127.0.0.1:3999"]
+    );
+
+    // ...and is ejected again if it's still failing.
+    health.record_failure(primary, "Connection refused", true);
+
+    assert_eq!(
+        upstreams(health.available_targets(&proxy, &settings)),
+        vec!["http://127.0.0.1:3999"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_health_is_kept_per_server() -> Result<(), Box<dyn Error>> {
+    let proxy = ProxyConfig {
+        targets: vec![UpstreamTarget::from_str(":3999")?],
+        ..ProxyConfig::from_str("api:3998")?
+    };
+
+    let settings = Settings {
+        host: String::from("127.0.0.1"),
+        ..Settings::default()
+    };
+
+    let health = Health::new();
+    let other = Health::new();
+
+    health.record_failure("http://127.0.0.1:3998", "Connection refused", true);
+
+    assert_eq!(health.available_targets(&proxy, &settings).len(), 1);
+    assert_eq!(other.available_targets(&proxy, &settings).len(), 2);
+    assert_eq!(
+        other.get("http://127.0.0.1:3998").status,
+        HealthStatus::Unknown
+    );
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use joubini::logging;
+use std::{error::Error, fs::File};
+
+#[test]
+fn test_invalid_log_level() -> Result<(), Box<dyn Error>> {
+    assert!(logging::init(Some("joubini=loud")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_no_colour_when_not_a_terminal() -> Result<(), Box<dyn Error>> {
+    let file = File::open("tests/config.yml")?;
+
+    assert!(!logging::use_colour(&file));
+
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::{error::Error, str::FromStr};
+
+use joubini::{
+    router::{find_proxy, matches, matches_host, shadowed_proxies},
+    settings::ProxyConfig,
+};
+
+#[test]
+fn test_match_path_segments() -> Result<(), Box<dyn Error>> {
+    assert!(matches("/", "/"));
+    assert!(matches("/", "/api"));
+    assert!(matches("/api", "/api"));
+    assert!(matches("/api", "/api/"));
+    assert!(matches("/api", "/api/users"));
+    assert!(matches("/api/", "/api/users"));
+    assert!(matches("/api/v1", "/api/v1/users"));
+
+    assert!(!matches("/api", "/"));
+    assert!(!matches("/api", "/apiary"));
+    assert!(!matches("/api/v1", "/api/v10"));
+    assert!(!matches("/api/v1", "/api"));
+
+    Ok(())
+}
+
+#[test]
+fn test_find_most_specific_proxy() -> Result<(), Box<dyn Error>> {
+    let proxies = vec![
+        ProxyConfig::from_str("myapp/api:3001/api")?,
+        ProxyConfig::from_str("myapp:3000/ui")?,
+        ProxyConfig::from_str(":3002")?,
+    ];
+
+    assert_eq!(
+        find_proxy(None, "/myapp/api/users", &proxies)
+            .unwrap()
+            .remote_port,
+        3001
+    );
+    assert_eq!(
+        find_proxy(None, "/myapp/apiary", &proxies)
+            .unwrap()
+            .remote_port,
+        3000
+    );
+    assert_eq!(
+        find_proxy(None, "/myapp", &proxies).unwrap().remote_port,
+        3000
+    );
+    assert_eq!(
+        find_proxy(None, "/other", &proxies).unwrap().remote_port,
+        3002
+    );
+
+    let mut reversed = proxies.clone();
+    reversed.reverse();
+
+    for path in ["/myapp/api/users", "/myapp/apiary", "/myapp", "/other"] {
+        assert_eq!(
+            find_proxy(None, path, &proxies),
+            find_proxy(None, path, &reversed)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_find_no_proxy() -> Result<(), Box<dyn Error>> {
+    let proxies = vec![ProxyConfig::from_str("api:3000")?];
+
+    assert!(find_proxy(None, "/apiary", &proxies).is_none());
+    assert!(find_proxy(None, "/", &proxies).is_none());
+    assert!(find_proxy(None, "/", &[]).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_last_declared_proxy_wins_tie() -> Result<(), Box<dyn Error>> {
+    let proxies = vec![
+        ProxyConfig::from_str("api:3000")?,
+        ProxyConfig::from_str("api/:3001")?,
+    ];
+
+    assert_eq!(
+        find_proxy(None, "/api/users", &proxies)
+            .unwrap()
+            .remote_port,
+        3001
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shadowed_proxies() -> Result<(), Box<dyn Error>> {
+    let proxies = vec![
+        ProxyConfig::from_str("api:3000")?,
+        ProxyConfig::from_str("api/v1:3001")?,
+        ProxyConfig::from_str("api/:3002")?,
+    ];
+
+    let shadowed = shadowed_proxies(&proxies);
+
+    assert_eq!(shadowed, vec![(&proxies[0], &proxies[2])]);
+
+    let hosts = vec![
+        ProxyConfig::from_str("//app.localhost/api:3000")?,
+        ProxyConfig::from_str("api:3001")?,
+        ProxyConfig::from_str("//api.localhost/api:3002")?,
+    ];
+
+    assert!(shadowed_proxies(&hosts).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_match_host() -> Result<(), Box<dyn Error>> {
+    assert!(matches_host("app.localhost", "app.localhost"));
+    assert!(matches_host("*.localhost", "app.localhost"));
+    assert!(matches_host("*.localhost", "api.app.localhost"));
+
+    assert!(!matches_host("app.localhost", "api.localhost"));
+    assert!(!matches_host("*.localhost", "localhost"));
+    assert!(!matches_host("*.localhost", "applocalhost"));
+    assert!(!matches_host("*.localhost", ".localhost"));
+
+    Ok(())
+}
+
+#[test]
+fn test_find_proxy_by_host() -> Result<(), Box<dyn Error>> {
+    let proxies = vec![
+        ProxyConfig::from_str("api:3000")?,
+        ProxyConfig::from_str("//*.localhost:3001")?,
+        ProxyConfig::from_str("//*.app.localhost:3002")?,
+        ProxyConfig::from_str("//app.localhost:3003")?,
+        ProxyConfig::from_str("//app.localhost/api:3004")?,
+    ];
+
+    let port =
+        |host, path| find_proxy(host, path, &proxies).map(|p| p.remote_port);
+
+    assert_eq!(port(Some("app.localhost"), "/"), Some(3003));
+    assert_eq!(port(Some("app.localhost"), "/api/users"), Some(3004));
+    assert_eq!(port(Some("api.app.localhost"), "/api"), Some(3002));
+    assert_eq!(port(Some("other.localhost"), "/api"), Some(3001));
+    assert_eq!(port(Some("example.com"), "/api"), Some(3000));
+    assert_eq!(port(None, "/api"), Some(3000));
+    assert_eq!(port(Some("example.com"), "/"), None);
+
+    Ok(())
+}
@@ -1,18 +1,39 @@
 use actix_web::http::header::{self, HeaderMap};
 use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::service::service_fn;
 use hyper::Uri;
-use joubini::server::start;
-use joubini::settings::{ProxyConfig, Settings};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use joubini::balancer::Balancer;
+use joubini::health::Health;
+use joubini::reload;
+use joubini::server::{serve, start, start_with_shutdown, ProxyServer};
+use joubini::settings::{
+    get_settings, AccessLogConfig, AccessLogFormat, AdminConfig,
+    AffinityConfig, CertificateConfig, HealthCheckConfig, ListenerConfig,
+    LoadBalancing, ProxyConfig, Settings, UpstreamTarget, UpstreamTls,
+};
+use joubini::shutdown::ShutdownHandle;
 use reqwest::header::HeaderName;
 use reqwest::StatusCode;
 use serial_test::serial;
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fs;
 use std::net::TcpListener;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
 
 static HOP_HEADERS: [HeaderName; 7] = [
     HeaderName::from_static("keep-alive"),
@@ -50,6 +71,88 @@ fn test_map_proxy_uri() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn test_map_proxy_uri_prefix_only() -> Result<(), Box<dyn Error>> {
+    let proxy = ProxyConfig::from_str("api:3000/v1")?;
+
+    let cases = [
+        ("/api", "/v1"),
+        ("/api/", "/v1/"),
+        ("/api/users/api", "/v1/users/api"),
+        ("/api/users?next=/api", "/v1/users?next=/api"),
+        ("/api/a%2Fb%20c?q=%2Fapi", "/v1/a%2Fb%20c?q=%2Fapi"),
+    ];
+
+    for (req_uri, expected) in cases {
+        let mapped_uri =
+            joubini::server::map_proxy_uri(&Uri::from_static(req_uri), &proxy)?;
+        assert_eq!(mapped_uri, Uri::from_static(expected));
+    }
+
+    let root_proxy = ProxyConfig::from_str(":3000")?;
+    let mapped_uri = joubini::server::map_proxy_uri(
+        &Uri::from_static("/api?x=1"),
+        &root_proxy,
+    )?;
+    assert_eq!(mapped_uri, Uri::from_static("/api?x=1"));
+
+    let root_to_path_proxy = ProxyConfig::from_str(":3000/ui")?;
+    let mapped_uri = joubini::server::map_proxy_uri(
+        &Uri::from_static("/"),
+        &root_to_path_proxy,
+    )?;
+    assert_eq!(mapped_uri, Uri::from_static("/ui"));
+
+    let nested_proxy = ProxyConfig::from_str("api:3000")?;
+    let mapped_uri = joubini::server::map_proxy_uri(
+        &Uri::from_static("/api"),
+        &nested_proxy,
+    )?;
+    assert_eq!(mapped_uri, Uri::from_static("/"));
+
+    assert!(joubini::server::map_proxy_uri(
+        &Uri::from_static("/apiary"),
+        &proxy
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_uri_rewrite_preserves_query() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("api:3031/echo-uri/api")
+            .expect("unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_remote(3031, "/").await;
+    start_joubini(settings).await;
+
+    let res = reqwest::get(
+        "http://localhost:7878/api/users/api?next=/api&q=a%2Fb%20c",
+    )
+    .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.text().await?,
+        "/echo-uri/api/users/api?next=/api&q=a%2Fb%20c"
+    );
+
+    let res = reqwest::get("http://localhost:7878/unmapped").await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
 #[serial]
 #[tokio::test]
 async fn test_headers_updated() -> Result<(), Box<dyn Error>> {
@@ -61,6 +164,7 @@ async fn test_headers_updated() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![ProxyConfig::from_str(":3015").unwrap()],
+        ..Settings::default()
     };
 
     start_remote(3015, "/").await;
@@ -100,15 +204,87 @@ async fn test_fail_when_no_remote_server() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![ProxyConfig::from_str(":3011").unwrap()],
+        ..Settings::default()
+    };
+
+    start_joubini(settings).await;
+
+    let client = reqwest::Client::new();
+
+    let res = client.get("http://localhost:7878").send().await?;
+
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    assert!(res.text().await?.contains("http://localhost:3011/"));
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_bad_gateway_json_body() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("api:3025/v1").unwrap()],
+        ..Settings::default()
     };
 
     start_joubini(settings).await;
 
     let client = reqwest::Client::new();
 
-    let res = client.get("http://localhost:7878").send().await;
+    let res = client
+        .get("http://localhost:7878/api/users")
+        .header(header::ACCEPT, "application/json")
+        .send()
+        .await?;
+
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+
+    let body: serde_json::Value = res.json().await?;
+
+    assert_eq!(body["status"], 502);
+    assert_eq!(body["error"], "Bad Gateway");
+    assert_eq!(body["route"], "/api");
+    assert_eq!(body["upstream"], "http://localhost:3025/v1/users");
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_gateway_timeout() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str(":3026").unwrap()],
+        response_timeout: 1,
+        ..Settings::default()
+    };
+
+    let listener = tokio::net::TcpListener::bind("localhost:3026").await?;
+
+    tokio::spawn(async move {
+        let mut streams = vec![];
+
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
 
-    assert!(res.is_err());
+    start_joubini(settings).await;
+
+    let res = reqwest::get("http://localhost:7878").await?;
+
+    assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
 
     Ok(())
 }
@@ -124,15 +300,106 @@ async fn test_fail_when_no_proxy_specified() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![],
+        ..Settings::default()
+    };
+
+    start_joubini(settings).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client.get("http://localhost:7878").send().await?;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_not_found_lists_proxies_in_dev_mode() -> Result<(), Box<dyn Error>>
+{
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![
+            ProxyConfig::from_str("api:3000/v1").unwrap(),
+            ProxyConfig::from_str("admin:3001").unwrap(),
+        ],
+        dev: true,
+        ..Settings::default()
     };
 
     start_joubini(settings).await;
 
     let client = reqwest::Client::new();
 
-    let response = client.get("http://localhost:7878").send().await;
+    let res = client
+        .get("http://localhost:7878/missing")
+        .header(header::ACCEPT, "application/json")
+        .send()
+        .await?;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let body: serde_json::Value = res.json().await?;
+
+    assert_eq!(
+        body["routes"],
+        serde_json::json!(["/api -> :3000/v1", "/admin -> :3001/"])
+    );
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_fallback_proxy() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("api:3027").unwrap()],
+        fallback: Some(ProxyConfig::from_str(":3028").unwrap()),
+        ..Settings::default()
+    };
+
+    start_remote(3028, "/fallback").await;
+    start_joubini(settings).await;
+
+    let res = reqwest::get("http://localhost:7878/fallback").await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    Ok(())
+}
 
-    assert!(response.is_err());
+#[serial]
+#[tokio::test]
+async fn test_fallback_proxy_with_local_path() -> Result<(), Box<dyn Error>> {
+    let result = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            fallback: Some(ProxyConfig::from_str("app:3028").unwrap()),
+            ..Settings::default()
+        })
+        .bind()
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(joubini::error::Error::ParseError(
+            joubini::error::ParseError::FallbackLocalPath(_)
+        ))
+    ));
 
     Ok(())
 }
@@ -148,6 +415,7 @@ async fn test_post_json() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![ProxyConfig::from_str(":3009").unwrap()],
+        ..Settings::default()
     };
 
     start_remote(3009, "/").await;
@@ -193,6 +461,7 @@ async fn test_post_form() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![ProxyConfig::from_str(":3010").unwrap()],
+        ..Settings::default()
     };
 
     start_remote(3010, "/").await;
@@ -237,6 +506,7 @@ async fn test_only_port_mapping() -> Result<(), Box<dyn Error>> {
         pem: None,
         key: None,
         proxies: vec![ProxyConfig::from_str(":3000").unwrap()],
+        ..Settings::default()
     };
 
     start_remote(3000, "/").await;
@@ -267,6 +537,7 @@ async fn test_path_to_port_mapping() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str("foo:3001")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_remote(3001, "/").await;
@@ -302,6 +573,7 @@ async fn test_path_to_path_mapping() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str("bar:3002/bar")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_remote(3002, "/bar").await;
@@ -337,6 +609,7 @@ async fn test_rename_path_mapping() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str("baz:3003/qux")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_remote(3003, "/qux").await;
@@ -372,6 +645,7 @@ async fn test_shallow_to_deep_path_mapping() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str("foo:3004/bar/baz/qux")
             .expect("Unable to parse proxy config from string")],
+        ..Settings::default()
     };
 
     start_remote(3004, "/bar/baz/qux").await;
@@ -407,6 +681,7 @@ async fn test_deep_to_shallow_path_mapping() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str("foo/bar/baz:3005/qux")
             .expect("Unable to parse proxy settings from provided string")],
+        ..Settings::default()
     };
 
     start_remote(3005, "/qux").await;
@@ -448,6 +723,7 @@ async fn test_nested_matching_path_mappings() -> Result<(), Box<dyn Error>> {
             ProxyConfig::from_str("foo/bar:3006/baz")
                 .expect("unable to parse proxy string"),
         ],
+        ..Settings::default()
     };
 
     start_remote(3006, "/baz").await;
@@ -497,6 +773,89 @@ async fn test_nested_matching_path_mappings() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[serial]
+#[tokio::test]
+async fn test_most_specific_path_mapping_regardless_of_order(
+) -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![
+            ProxyConfig::from_str("myapp/api:3029/api")
+                .expect("unable to parse proxy string"),
+            ProxyConfig::from_str("myapp:3030/ui")
+                .expect("unable to parse proxy string"),
+        ],
+        ..Settings::default()
+    };
+
+    start_remote(3029, "/api").await;
+    start_remote(3030, "/ui/apiary").await;
+    start_joubini(settings).await;
+
+    let response_api = reqwest::get("http://localhost:7878/myapp/api").await?;
+    assert_eq!(response_api.status(), StatusCode::OK);
+    assert_eq!(response_api.text().await?, "get_ok");
+
+    let response_apiary =
+        reqwest::get("http://localhost:7878/myapp/apiary").await?;
+    assert_eq!(response_apiary.status(), StatusCode::OK);
+    assert_eq!(response_apiary.text().await?, "get_ok");
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_host_based_routing() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![
+            ProxyConfig::from_str("//app.localhost:3032/app")
+                .expect("unable to parse proxy string"),
+            ProxyConfig::from_str("//*.localhost:3033/wildcard")
+                .expect("unable to parse proxy string"),
+        ],
+        ..Settings::default()
+    };
+
+    start_remote(3032, "/app").await;
+    start_remote(3033, "/wildcard").await;
+    start_joubini(settings).await;
+
+    let client = reqwest::Client::new();
+
+    let res = client
+        .get("http://localhost:7878/")
+        .header("host", "App.localhost:7878")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    let res = client
+        .get("http://localhost:7878/")
+        .header("host", "api.localhost")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    let res = client.get("http://localhost:7878/").send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
 #[serial]
 #[tokio::test]
 async fn test_add_x_forwarded_for_header() -> Result<(), Box<dyn Error>> {
@@ -509,6 +868,7 @@ async fn test_add_x_forwarded_for_header() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str(":3012")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_joubini(settings).await;
@@ -535,6 +895,7 @@ async fn test_append_x_forwarded_for_header() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str(":3013")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_joubini(settings).await;
@@ -566,6 +927,7 @@ async fn test_response_codes() -> Result<(), Box<dyn Error>> {
         key: None,
         proxies: vec![ProxyConfig::from_str(":3014")
             .expect("Unable to parse proxy string")],
+        ..Settings::default()
     };
 
     start_joubini(settings).await;
@@ -612,6 +974,7 @@ async fn test_tls_server() -> Result<(), Box<dyn Error>> {
         tls: true,
         pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
         key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+        ..Settings::default()
     };
 
     start_remote(3016, "/").await;
@@ -642,73 +1005,1854 @@ async fn test_tls_server() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-// TODO: http2
-// let client = reqwest::Client::builder()
-//     .use_native_tls()
-//     .identity(cert)
-//     .add_root_certificate(ca)
-//     .pool_max_idle_per_host(0)
-// .http2_prior_knowledge()
-//     .build()?;
+#[serial]
+#[tokio::test]
+async fn test_tls_handshake_failures_are_not_fatal(
+) -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3034").unwrap()],
+        config: None,
+        tls: true,
+        pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
+        key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+        ..Settings::default()
+    };
 
-async fn start_joubini(settings: Settings) {
-    let listener = Arc::new(
-        tokio::net::TcpListener::bind(format!(
-            "localhost:{}",
-            settings.local_port
-        ))
-        .await
-        .expect("Unable to bind to local port"),
-    );
+    start_remote(3034, "/").await;
+    start_joubini(settings).await;
 
-    let settings = Arc::new(settings);
+    let failures = joubini::server::tls_handshake_failures();
 
-    tokio::spawn(async move {
-        loop {
-            start(listener.clone(), settings.clone())
-                .await
-                .expect("Unable to start server");
-        }
-    });
-}
+    // A client that never starts its handshake shouldn't block others.
+    let _stalled = tokio::net::TcpStream::connect("localhost:7878").await?;
 
-async fn start_remote(port: u16, path: &'static str) {
-    let listener = TcpListener::bind(format!("localhost:{}", port))
-        .expect("Unable to listen on port");
+    let mut invalid = tokio::net::TcpStream::connect("localhost:7878").await?;
+    invalid.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+    let mut buf = vec![];
+    let _ = invalid.read_to_end(&mut buf).await;
 
-    let server = HttpServer::new(move || {
-        App::new()
-            .route(path, web::get().to(get_ok))
-            .route("/json-post", web::post().to(post_json_ok))
-            .route("/form-post", web::post().to(post_form_ok))
-            .route("/add-forwarded", web::get().to(add_forwarded_ok))
-            .route("/append-forwarded", web::get().to(append_forwarded_ok))
-            .route("/301", web::get().to(handler_301))
-            .route("/404", web::get().to(handler_404))
-            .route("/500", web::get().to(handler_500))
-            .route("/headers", web::get().to(headers_ok))
-    })
-    .listen(listener)
-    .expect("Unable to start remote server")
-    .run();
+    let root = fs::read("/tmp/localCA.pem").unwrap();
 
-    tokio::spawn(server);
-}
+    let client = reqwest::Client::builder()
+        .use_native_tls()
+        .add_root_certificate(reqwest::Certificate::from_pem(&root)?)
+        .pool_max_idle_per_host(0)
+        .build()?;
 
-async fn handler_301() -> HttpResponse {
-    HttpResponse::MovedPermanently().finish()
-}
+    let res = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.get("https://localhost:7878").send(),
+    )
+    .await??;
 
-async fn handler_404() -> HttpResponse {
-    HttpResponse::NotFound().finish()
-}
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+    assert!(joubini::server::tls_handshake_failures() > failures);
 
-async fn handler_500() -> HttpResponse {
-    HttpResponse::InternalServerError().finish()
+    Ok(())
 }
 
-async fn get_ok() -> HttpResponse {
-    HttpResponse::Ok().body("get_ok")
+#[serial]
+#[tokio::test]
+async fn test_http_and_https_listeners() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        host: String::from("localhost"),
+        proxies: vec![ProxyConfig::from_str(":3035").unwrap()],
+        listeners: vec![
+            ListenerConfig {
+                port: 7878,
+                ..ListenerConfig::default()
+            },
+            ListenerConfig {
+                port: 7879,
+                redirect_to_https: Some(7880),
+                ..ListenerConfig::default()
+            },
+            ListenerConfig {
+                port: 7880,
+                tls: true,
+                pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
+                key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+                proxies: Some(vec![
+                    ProxyConfig::from_str("secure:3035/").unwrap()
+                ]),
+                ..ListenerConfig::default()
+            },
+        ],
+        ..Settings::default()
+    };
+
+    start_remote(3035, "/").await;
+
+    for listener_settings in settings.listener_settings() {
+        start_joubini(listener_settings).await;
+    }
+
+    let root = fs::read("/tmp/localCA.pem").unwrap();
+
+    let client = reqwest::Client::builder()
+        .use_native_tls()
+        .add_root_certificate(reqwest::Certificate::from_pem(&root)?)
+        .redirect(reqwest::redirect::Policy::none())
+        .pool_max_idle_per_host(0)
+        .build()?;
+
+    let res = client.get("http://localhost:7878/").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    let res = client
+        .get("http://localhost:7879/secure?a=b")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        res.headers().get("location").unwrap(),
+        "https://localhost:7880/secure?a=b"
+    );
+
+    let res = client.get("https://localhost:7880/secure").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    let res = client.get("https://localhost:7880/").send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_tls_server_with_local_ca() -> Result<(), Box<dyn Error>> {
+    let ca_dir = std::env::temp_dir()
+        .join(format!("joubini-server-ca-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&ca_dir);
+
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3036").unwrap()],
+        tls: true,
+        ca_dir: Some(ca_dir.clone()),
+        ..Settings::default()
+    };
+
+    let ca = joubini::ca::LocalCa::load_or_create(Some(&ca_dir))?;
+    let root = ca.root_pem().as_bytes();
+
+    start_remote(3036, "/").await;
+    start_joubini(settings).await;
+
+    let client = reqwest::Client::builder()
+        .use_native_tls()
+        .add_root_certificate(reqwest::Certificate::from_pem(root)?)
+        .pool_max_idle_per_host(0)
+        .build()?;
+
+    let res = client.get("https://localhost:7878").send().await?;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    assert!(ca_dir.join("certs").join("localhost.crt").exists());
+
+    fs::remove_dir_all(&ca_dir)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_tls_server_with_sni_certificates() -> Result<(), Box<dyn Error>> {
+    let ca_dir = std::env::temp_dir()
+        .join(format!("joubini-server-sni-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&ca_dir);
+
+    let ca = joubini::ca::LocalCa::load_or_create(Some(&ca_dir))?;
+    ca.certified_key("app.localhost")?;
+
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3037").unwrap()],
+        tls: true,
+        pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
+        key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+        certificates: vec![CertificateConfig {
+            hosts: vec![String::from("app.localhost")],
+            pem: ca_dir.join("certs").join("app.localhost.crt"),
+            key: ca_dir.join("certs").join("app.localhost.key"),
+        }],
+        ..Settings::default()
+    };
+
+    start_remote(3037, "/").await;
+    start_joubini(settings).await;
+
+    let addr = "127.0.0.1:7878".parse()?;
+
+    let sni_client = reqwest::Client::builder()
+        .use_native_tls()
+        .add_root_certificate(reqwest::Certificate::from_pem(
+            ca.root_pem().as_bytes(),
+        )?)
+        .tls_built_in_root_certs(false)
+        .resolve("app.localhost", addr)
+        .pool_max_idle_per_host(0)
+        .build()?;
+
+    let res = sni_client.get("https://app.localhost:7878").send().await?;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    let default_client = reqwest::Client::builder()
+        .use_native_tls()
+        .add_root_certificate(reqwest::Certificate::from_pem(&fs::read(
+            "/tmp/localCA.pem",
+        )?)?)
+        .pool_max_idle_per_host(0)
+        .build()?;
+
+    let res = default_client.get("https://localhost:7878").send().await?;
+    assert_eq!(res.status(), reqwest::StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    fs::remove_dir_all(&ca_dir)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_tls_server_key_formats_and_chains() -> Result<(), Box<dyn Error>>
+{
+    start_remote(3038, "/").await;
+
+    let fixtures = PathBuf::from("tests/fixtures/tls");
+
+    for (port, name, root) in [
+        (7878, "rsa-pkcs1", "rsa-pkcs1.crt"),
+        (7879, "ec-sec1", "ec-sec1.crt"),
+        (7880, "chain-pkcs8", "root.crt"),
+    ] {
+        let settings = Settings {
+            host: String::from("localhost"),
+            local_port: port,
+            proxies: vec![ProxyConfig::from_str(":3038").unwrap()],
+            tls: true,
+            pem: Some(fixtures.join(format!("{}.crt", name))),
+            key: Some(fixtures.join(format!("{}.key", name))),
+            ..Settings::default()
+        };
+
+        start_joubini(settings).await;
+
+        let client = reqwest::Client::builder()
+            .use_native_tls()
+            .add_root_certificate(reqwest::Certificate::from_pem(&fs::read(
+                fixtures.join(root),
+            )?)?)
+            .pool_max_idle_per_host(0)
+            .build()?;
+
+        let res = client
+            .get(format!("https://localhost:{}", port))
+            .send()
+            .await?;
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        assert_eq!(res.text().await?, "get_ok");
+    }
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_tls_server_invalid_key() -> Result<(), Box<dyn Error>> {
+    let listener =
+        Arc::new(tokio::net::TcpListener::bind("localhost:7878").await?);
+
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: true,
+        pem: Some(PathBuf::from("tests/fixtures/tls/rsa-pkcs1.crt")),
+        key: Some(PathBuf::from("tests/fixtures/tls/invalid.key")),
+        ..Settings::default()
+    };
+
+    let result = start(listener, Arc::new(settings)).await;
+
+    assert!(matches!(
+        result,
+        Err(joubini::error::Error::TlsError(
+            joubini::error::TlsError::InvalidPrivateKey(_)
+        ))
+    ));
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_reload_config() -> Result<(), Box<dyn Error>> {
+    let config = std::env::temp_dir()
+        .join(format!("joubini-reload-{}.yml", std::process::id()));
+
+    fs::write(
+        &config,
+        "host: localhost\nport: 7878\nproxies:\n  - a:3039/a\n",
+    )?;
+
+    let cli_args = vec![
+        OsString::from("joubini"),
+        OsString::from(format!("--config={}", config.display())),
+    ];
+
+    let settings = Arc::new(get_settings(cli_args.clone())?);
+    let listener =
+        Arc::new(tokio::net::TcpListener::bind("localhost:7878").await?);
+    let (settings_tx, settings_rx) = watch::channel(settings);
+
+    start_remote(3039, "/a").await;
+    start_remote(3040, "/b").await;
+
+    tokio::spawn(serve(
+        listener,
+        settings_rx,
+        Health::new(),
+        Balancer::new(),
+        ShutdownHandle::new(),
+    ));
+    tokio::spawn(reload::watch(
+        cli_args,
+        vec![settings_tx],
+        Duration::from_millis(50),
+    ));
+
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(0)
+        .build()?;
+
+    let res = client.get("http://localhost:7878/a").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    fs::write(
+        &config,
+        "host: localhost\nport: 7878\nproxies:\n  - b:3040/b\n  - c:3040/b\n",
+    )?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let res = client.get("http://localhost:7878/a").send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = client.get("http://localhost:7878/b").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Invalid edits are ignored and the previous settings stay in place.
+    fs::write(
+        &config,
+        "host: localhost\nport: 7878\nproxies:\n  - nonsense\n",
+    )?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let res = client.get("http://localhost:7878/b").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    fs::write(
+        &config,
+        "host: localhost\nport: 7879\nproxies:\n  - a:3039/a\n",
+    )?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let res = client.get("http://localhost:7878/b").send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    fs::remove_file(&config)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_reload_config_with_any_port() -> Result<(), Box<dyn Error>> {
+    let config = std::env::temp_dir().join(format!(
+        "joubini-reload-any-port-{}.yml",
+        std::process::id()
+    ));
+
+    fs::write(&config, "port: 0\nproxies:\n  - a:3039/a\n")?;
+
+    let cli_args = vec![
+        OsString::from("joubini"),
+        OsString::from(format!("--config={}", config.display())),
+    ];
+
+    let server = ProxyServer::builder()
+        .settings(get_settings(cli_args.clone())?)
+        .bind()
+        .await?;
+
+    fs::write(&config, "port: 0\nproxies:\n  - b:3040/b\n")?;
+
+    reload::reload(&cli_args, &server.settings_senders())?;
+
+    let settings = server.settings_senders()[0].borrow().clone();
+    assert_eq!(settings.local_port, server.local_addr().port());
+    assert_eq!(settings.proxies[0].local_path, "/b");
+
+    server.shutdown();
+    server.join().await?;
+
+    fs::remove_file(&config)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_upstream_tls_missing_ca() -> Result<(), Box<dyn Error>> {
+    let ca = PathBuf::from("tests/fixtures/tls/missing-ca.pem");
+
+    let result = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig {
+                tls: UpstreamTls {
+                    ca: Some(ca.clone()),
+                    ..UpstreamTls::default()
+                },
+                ..ProxyConfig::from_str("secure:https://127.0.0.1:3043")
+                    .unwrap()
+            }],
+            ..Settings::default()
+        })
+        .bind()
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(joubini::error::Error::TlsError(
+            joubini::error::TlsError::ReadFile(path, _)
+        )) if path == ca
+    ));
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_reload_keeps_admin_routes() -> Result<(), Box<dyn Error>> {
+    let config = std::env::temp_dir()
+        .join(format!("joubini-reload-admin-{}.yml", std::process::id()));
+
+    fs::write(
+        &config,
+        "port: 0\nadmin:\n  port: 0\nproxies:\n  - a:3039/a\n",
+    )?;
+
+    let cli_args = vec![
+        OsString::from("joubini"),
+        OsString::from(format!("--config={}", config.display())),
+    ];
+
+    start_remote(3039, "/a").await;
+    start_remote(3040, "/b").await;
+
+    let server = ProxyServer::builder()
+        .settings(get_settings(cli_args.clone())?)
+        .bind()
+        .await?;
+
+    let admin = format!("http://{}", server.admin_addr().unwrap());
+    let proxy = format!("http://{}", server.local_addr());
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(format!("{}/routes", admin))
+        .body("b:3040/b")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    fs::write(
+        &config,
+        "port: 0\nadmin:\n  port: 0\nproxies:\n  - c:3039/a\n",
+    )?;
+
+    reload::reload(&cli_args, &server.settings_senders())?;
+
+    let res = client.get(format!("{}/a", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = client.get(format!("{}/b", proxy)).send().await?;
+    assert_eq!(res.text().await?, "get_ok");
+    let res = client.get(format!("{}/c", proxy)).send().await?;
+    assert_eq!(res.text().await?, "get_ok");
+
+    server.shutdown();
+    server.join().await?;
+
+    fs::remove_file(&config)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_graceful_shutdown() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3041").unwrap()],
+        ..Settings::default()
+    };
+
+    start_remote(3041, "/").await;
+
+    let listener =
+        Arc::new(tokio::net::TcpListener::bind("localhost:7878").await?);
+    let shutdown = ShutdownHandle::new();
+
+    let server = tokio::spawn(start_with_shutdown(
+        listener,
+        Arc::new(settings),
+        shutdown.clone(),
+    ));
+
+    // Build the client up front, as doing so can take longer than the
+    // request is given to reach the server before shutting down.
+    let client = reqwest::Client::new();
+    let in_flight =
+        tokio::spawn(client.get("http://localhost:7878/slow/500").send());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.shutdown();
+
+    let res = in_flight.await??;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "slow_ok");
+
+    server.await??;
+
+    assert!(shutdown.is_shutdown());
+    assert_eq!(shutdown.active_connections(), 0);
+    assert!(reqwest::get("http://localhost:7878/").await.is_err());
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_graceful_shutdown_deadline() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3042").unwrap()],
+        shutdown_timeout: 1,
+        ..Settings::default()
+    };
+
+    start_remote(3042, "/").await;
+
+    let listener =
+        Arc::new(tokio::net::TcpListener::bind("localhost:7878").await?);
+    let shutdown = ShutdownHandle::new();
+
+    let server = tokio::spawn(start_with_shutdown(
+        listener,
+        Arc::new(settings),
+        shutdown.clone(),
+    ));
+
+    let client = reqwest::Client::new();
+    let in_flight =
+        tokio::spawn(client.get("http://localhost:7878/slow/5000").send());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.shutdown();
+
+    tokio::time::timeout(Duration::from_secs(3), server).await???;
+
+    assert!(in_flight.await?.is_err());
+    assert_eq!(shutdown.active_connections(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_server_builder() -> Result<(), Box<dyn Error>> {
+    start_remote(3043, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig::from_str(":3043").unwrap()],
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let local_addr = server.local_addr();
+    assert_ne!(local_addr.port(), 0);
+
+    let res = reqwest::get(format!("http://{}/", local_addr)).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    server.shutdown();
+    server.join().await?;
+
+    assert!(reqwest::get(format!("http://{}/", local_addr))
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_server_builder_multiple_listeners(
+) -> Result<(), Box<dyn Error>> {
+    start_remote(3044, "/").await;
+
+    let shutdown = ShutdownHandle::new();
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            proxies: vec![ProxyConfig::from_str(":3044").unwrap()],
+            listeners: vec![
+                ListenerConfig {
+                    port: 0,
+                    ..ListenerConfig::default()
+                },
+                ListenerConfig {
+                    port: 0,
+                    ..ListenerConfig::default()
+                },
+            ],
+            ..Settings::default()
+        })
+        .shutdown_handle(shutdown.clone())
+        .bind()
+        .await?;
+
+    let local_addrs = server.local_addrs().to_vec();
+    assert_eq!(local_addrs.len(), 2);
+    assert_ne!(local_addrs[0], local_addrs[1]);
+
+    for local_addr in &local_addrs {
+        let res = reqwest::get(format!("http://{}/", local_addr)).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    shutdown.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_server_builder_invalid_tls() -> Result<(), Box<dyn Error>> {
+    let result = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            tls: true,
+            pem: Some(PathBuf::from("tests/fixtures/tls/root.crt")),
+            key: None,
+            ..Settings::default()
+        })
+        .bind()
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_json_access_log() -> Result<(), Box<dyn Error>> {
+    let access_log = std::env::temp_dir()
+        .join(format!("joubini-access-{}.json", std::process::id()));
+    let _ = fs::remove_file(&access_log);
+
+    start_remote(3045, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig::from_str("api:3045").unwrap()],
+            access_log: AccessLogConfig {
+                format: AccessLogFormat::Json,
+                path: Some(access_log.clone()),
+            },
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(format!("http://{}/api/json-post", server.local_addr()))
+        .header("user-agent", "joubini-test")
+        .json(&PostData {
+            data: String::from("post_data"),
+        })
+        .send()
+        .await?;
+    let body = res.text().await?;
+
+    let res = client
+        .get(format!("http://{}/missing?q=1", server.local_addr()))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    res.text().await?;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let entries = fs::read_to_string(&access_log)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+    assert_eq!(entries.len(), 2);
+
+    assert!(entries[0]["request_id"].is_u64());
+    assert_ne!(entries[0]["request_id"], entries[1]["request_id"]);
+    assert_eq!(entries[0]["method"], "POST");
+    assert_eq!(entries[0]["uri"], "/api/json-post");
+    assert_eq!(entries[0]["version"], "HTTP/1.1");
+    assert_eq!(entries[0]["status"], 200);
+    assert_eq!(entries[0]["bytes_in"], r#"{"data":"post_data"}"#.len());
+    assert_eq!(entries[0]["bytes_out"], body.len());
+    assert_eq!(entries[0]["route"], "/api -> :3045/");
+    assert_eq!(entries[0]["upstream"], "http://127.0.0.1:3045/json-post");
+    assert_eq!(entries[0]["user_agent"], "joubini-test");
+    assert!(entries[0]["client_addr"]
+        .as_str()
+        .is_some_and(|a| a.starts_with("127.0.0.1:")));
+    assert!(entries[0]["duration_ms"].is_f64());
+    assert!(entries[0]["timestamp"].is_string());
+
+    assert_eq!(entries[1]["uri"], "/missing?q=1");
+    assert_eq!(entries[1]["status"], 404);
+    assert_eq!(entries[1]["error"], "no matching proxy");
+    assert!(entries[1].get("upstream").is_none());
+
+    server.shutdown();
+    server.join().await?;
+
+    fs::remove_file(&access_log)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_combined_access_log() -> Result<(), Box<dyn Error>> {
+    let access_log = std::env::temp_dir()
+        .join(format!("joubini-access-{}.log", std::process::id()));
+    let _ = fs::remove_file(&access_log);
+
+    start_remote(3046, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig::from_str(":3046").unwrap()],
+            access_log: AccessLogConfig {
+                format: AccessLogFormat::Combined,
+                path: Some(access_log.clone()),
+            },
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let res = reqwest::Client::new()
+        .get(format!("http://{}/?a=b", server.local_addr()))
+        .header("referer", "http://example.com/")
+        .header("user-agent", "joubini \"test\"")
+        .send()
+        .await?;
+    assert_eq!(res.text().await?, "get_ok");
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let line = fs::read_to_string(&access_log)?;
+
+    assert!(line.starts_with("127.0.0.1 - - ["));
+    assert!(line.ends_with(
+        "\"GET /?a=b HTTP/1.1\" 200 6 \"http://example.com/\" \"joubini \\\"test\\\"\"\n"
+    ));
+
+    server.shutdown();
+    server.join().await?;
+
+    fs::remove_file(&access_log)?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_admin_metrics() -> Result<(), Box<dyn Error>> {
+    start_remote(3047, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig::from_str("metrics:3047").unwrap()],
+            admin: Some(AdminConfig {
+                host: None,
+                port: 0,
+            }),
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let admin_addr = server.admin_addr().expect("Admin listener not bound");
+    let client = reqwest::Client::new();
+
+    for _ in 0..2 {
+        let res = client
+            .get(format!("http://{}/metrics", server.local_addr()))
+            .send()
+            .await?;
+        assert_eq!(res.text().await?, "get_ok");
+    }
+
+    let res = client
+        .get(format!("http://{}/metrics/404", server.local_addr()))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    res.text().await?;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let res = client
+        .get(format!("http://{}/metrics", admin_addr))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE.as_str()],
+        "text/plain; version=0.0.4; charset=utf-8"
+    );
+
+    let body = res.text().await?;
+    let labels =
+        "route=\"/metrics\",upstream=\"http://127.0.0.1:3047\",method=\"GET\"";
+
+    assert!(body.contains(&format!(
+        "joubini_requests_total{{{},status=\"2xx\"}} 2",
+        labels
+    )));
+    assert!(body.contains(&format!(
+        "joubini_requests_total{{{},status=\"4xx\"}} 1",
+        labels
+    )));
+    assert!(body.contains(&format!(
+        "joubini_request_duration_seconds_bucket{{{},status=\"2xx\",le=\"+Inf\"}} 2",
+        labels
+    )));
+    assert!(body.contains(&format!(
+        "joubini_request_duration_seconds_count{{{},status=\"2xx\"}} 2",
+        labels
+    )));
+    assert!(body.contains("# TYPE joubini_active_connections gauge"));
+    assert!(body.contains("# TYPE joubini_active_websockets gauge"));
+    assert!(body.contains("joubini_tls_handshake_failures_total "));
+
+    let res = client
+        .get(format!("http://{}/nothing", admin_addr))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_admin_routes() -> Result<(), Box<dyn Error>> {
+    start_remote(3048, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig::from_str("api:3048").unwrap()],
+            admin: Some(AdminConfig {
+                host: None,
+                port: 0,
+            }),
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let admin = format!("http://{}", server.admin_addr().unwrap());
+    let proxy = format!("http://{}", server.local_addr());
+
+    // Route changes apply to connections that are already open.
+    let client = reqwest::Client::new();
+
+    let routes: serde_json::Value = client
+        .get(format!("{}/routes", admin))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(routes[0]["listener"], server.local_addr().to_string());
+    assert_eq!(routes[0]["routes"][0]["route"], "/api -> :3048/");
+    assert_eq!(routes[0]["routes"][0]["upstream"], "http://127.0.0.1:3048");
+
+    let res = client.get(format!("{}/new", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = client
+        .post(format!("{}/routes", admin))
+        .body("new:3048")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = client.get(format!("{}/new", proxy)).send().await?;
+    assert_eq!(res.text().await?, "get_ok");
+
+    let res = client
+        .post(format!("{}/routes", admin))
+        .body("new:3048/404")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let res = client
+        .post(format!("{}/routes", admin))
+        .body("not a proxy")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = client
+        .put(format!("{}/routes", admin))
+        .body("new:3048/404")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client.get(format!("{}/new", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = client
+        .put(format!("{}/routes", admin))
+        .body("missing:3048")
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = client
+        .delete(format!("{}/routes/new", admin))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client
+        .delete(format!("{}/routes/new", admin))
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = client.get(format!("{}/new", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(res.text().await?.contains("No proxy configured for /new"));
+
+    let routes: serde_json::Value = client
+        .get(format!("{}/routes", admin))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(routes[0]["routes"].as_array().unwrap().len(), 1);
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_admin_upstreams() -> Result<(), Box<dyn Error>> {
+    start_remote(3049, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![
+                ProxyConfig::from_str("up:3049").unwrap(),
+                ProxyConfig::from_str("down:3998").unwrap(),
+                ProxyConfig::from_str("unused:3997").unwrap(),
+            ],
+            admin: Some(AdminConfig {
+                host: None,
+                port: 0,
+            }),
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let admin = format!("http://{}", server.admin_addr().unwrap());
+    let proxy = format!("http://{}", server.local_addr());
+    let client = reqwest::Client::new();
+
+    let res = client.get(format!("{}/up", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client.get(format!("{}/down", proxy)).send().await?;
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+
+    let upstreams: serde_json::Value = client
+        .get(format!("{}/upstreams", admin))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    assert_eq!(upstreams[0]["upstream"], "http://127.0.0.1:3049");
+    assert_eq!(upstreams[0]["routes"][0], "/up -> :3049/");
+    assert_eq!(upstreams[0]["status"], "healthy");
+    assert_eq!(upstreams[1]["upstream"], "http://127.0.0.1:3998");
+    assert_eq!(upstreams[1]["status"], "unhealthy");
+    assert_eq!(upstreams[1]["consecutive_failures"], 1);
+    assert!(upstreams[1]["last_error"].is_string());
+    assert_eq!(upstreams[2]["upstream"], "http://127.0.0.1:3997");
+    assert_eq!(upstreams[2]["status"], "unknown");
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_failover_to_next_target() -> Result<(), Box<dyn Error>> {
+    start_remote(3051, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig {
+                targets: vec![UpstreamTarget::from_str(":3051").unwrap()],
+                ..ProxyConfig::from_str("failover:3050").unwrap()
+            }],
+            admin: Some(AdminConfig {
+                host: None,
+                port: 0,
+            }),
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let proxy = format!("http://{}", server.local_addr());
+    let client = reqwest::Client::new();
+
+    // The primary is down, so the request fails over within the same
+    // request, and later requests skip the primary altogether.
+    for _ in 0..2 {
+        let res = client
+            .get(format!("{}/failover/host", proxy))
+            .send()
+            .await?;
+        assert_eq!(res.text().await?, "127.0.0.1:3051");
+    }
+
+    let upstreams: serde_json::Value = client
+        .get(format!("http://{}/upstreams", server.admin_addr().unwrap()))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    assert_eq!(upstreams[0]["upstream"], "http://127.0.0.1:3050");
+    assert_eq!(upstreams[0]["status"], "unhealthy");
+    assert_eq!(upstreams[0]["consecutive_failures"], 1);
+    assert_eq!(upstreams[1]["upstream"], "http://127.0.0.1:3051");
+    assert_eq!(upstreams[1]["status"], "healthy");
+
+    // Requests with a body can't be replayed, but the ejected primary is
+    // skipped for them too.
+    let res = client
+        .post(format!("{}/failover/json-post", proxy))
+        .json(&PostData {
+            data: String::from("post_data"),
+        })
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_active_health_checks() -> Result<(), Box<dyn Error>> {
+    start_remote(3053, "/").await;
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![ProxyConfig {
+                targets: vec![UpstreamTarget::from_str(":3053").unwrap()],
+                health_check: Some(HealthCheckConfig {
+                    path: String::from("/"),
+                    interval: 1,
+                    timeout: 1,
+                    healthy_threshold: 1,
+                    unhealthy_threshold: 1,
+                }),
+                ..ProxyConfig::from_str("checked:3052").unwrap()
+            }],
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let proxy = format!("http://{}", server.local_addr());
+    let client = reqwest::Client::new();
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let res = client.get(format!("{}/checked/host", proxy)).send().await?;
+    assert_eq!(res.text().await?, "127.0.0.1:3053");
+
+    // Once the primary passes a health check, traffic goes back to it.
+    start_remote(3052, "/").await;
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let res = client.get(format!("{}/checked/host", proxy)).send().await?;
+    assert_eq!(res.text().await?, "127.0.0.1:3052");
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_active_health_checks_without_builder(
+) -> Result<(), Box<dyn Error>> {
+    // Only the target on 3059 passes the health check, as 3060 doesn't serve
+    // the path.
+    start_remote(3059, "/ready").await;
+    start_remote(3060, "/").await;
+
+    let settings = Settings {
+        host: String::from("127.0.0.1"),
+        local_port: 0,
+        proxies: vec![ProxyConfig {
+            targets: vec![UpstreamTarget::from_str(":3059").unwrap()],
+            health_check: Some(HealthCheckConfig {
+                path: String::from("/ready"),
+                interval: 1,
+                timeout: 1,
+                healthy_threshold: 1,
+                unhealthy_threshold: 1,
+            }),
+            ..ProxyConfig::from_str("checked:3060").unwrap()
+        }],
+        ..Settings::default()
+    };
+
+    let listener =
+        Arc::new(tokio::net::TcpListener::bind("127.0.0.1:0").await?);
+    let proxy = format!("http://{}", listener.local_addr()?);
+    let shutdown = ShutdownHandle::new();
+
+    let server = tokio::spawn(start_with_shutdown(
+        listener,
+        Arc::new(settings),
+        shutdown.clone(),
+    ));
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let res = reqwest::get(format!("{}/checked/host", proxy)).await?;
+    assert_eq!(res.text().await?, "127.0.0.1:3059");
+
+    shutdown.shutdown();
+    server.await??;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_load_balancing() -> Result<(), Box<dyn Error>> {
+    start_remote(3054, "/").await;
+    start_remote(3055, "/").await;
+
+    let balanced = |definition: &str, strategy: &str| ProxyConfig {
+        targets: vec![UpstreamTarget::from_str(":3055").unwrap()],
+        load_balancing: LoadBalancing::from_str(strategy).unwrap(),
+        ..ProxyConfig::from_str(definition).unwrap()
+    };
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![
+                balanced("rr:3054", "round_robin"),
+                balanced("hash:3054", "hash:header:x-user-id"),
+                balanced("least:3054", "least_connections"),
+                balanced("random:3054", "random"),
+            ],
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let proxy = format!("http://{}", server.local_addr());
+    let client = reqwest::Client::new();
+
+    let mut hosts = vec![];
+
+    for _ in 0..4 {
+        let res = client.get(format!("{}/rr/host", proxy)).send().await?;
+        hosts.push(res.text().await?);
+    }
+
+    assert_ne!(hosts[0], hosts[1]);
+    assert_eq!(hosts[0], hosts[2]);
+    assert_eq!(hosts[1], hosts[3]);
+
+    for user in ["alice", "bob", "carol", "dave"] {
+        let mut hosts = vec![];
+
+        for _ in 0..3 {
+            let res = client
+                .get(format!("{}/hash/host", proxy))
+                .header("x-user-id", user)
+                .send()
+                .await?;
+            hosts.push(res.text().await?);
+        }
+
+        assert!(hosts.iter().all(|h| *h == hosts[0]));
+    }
+
+    // The slow request keeps the first target busy, so the next request
+    // goes to the other one.
+    let slow =
+        tokio::spawn(client.get(format!("{}/least/slow/500", proxy)).send());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let res = client.get(format!("{}/least/host", proxy)).send().await?;
+    assert_eq!(res.text().await?, "127.0.0.1:3055");
+    assert_eq!(slow.await??.text().await?, "slow_ok");
+
+    for _ in 0..4 {
+        let res = client.get(format!("{}/random/host", proxy)).send().await?;
+        assert!(["127.0.0.1:3054", "127.0.0.1:3055"]
+            .contains(&res.text().await?.as_str()));
+    }
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_sticky_sessions() -> Result<(), Box<dyn Error>> {
+    start_remote(3056, "/").await;
+    start_remote(3057, "/").await;
+
+    let proxy_config = ProxyConfig {
+        targets: vec![
+            UpstreamTarget::from_str(":3057").unwrap(),
+            UpstreamTarget::from_str(":3058").unwrap(),
+        ],
+        load_balancing: LoadBalancing::RoundRobin,
+        affinity: Some(AffinityConfig {
+            cookie: String::from("affinity"),
+            secret: Some(String::from("s3cret")),
+        }),
+        ..ProxyConfig::from_str("sticky:3056").unwrap()
+    };
+
+    let server = ProxyServer::builder()
+        .settings(Settings {
+            host: String::from("127.0.0.1"),
+            local_port: 0,
+            proxies: vec![proxy_config.clone()],
+            ..Settings::default()
+        })
+        .bind()
+        .await?;
+
+    let url = format!("http://{}/sticky/host", server.local_addr());
+    let client = reqwest::Client::new();
+
+    let res = client.get(&url).send().await?;
+    let cookie = res.headers()[header::SET_COOKIE.as_str()].to_str()?;
+    let cookie = String::from(cookie.split(';').next().unwrap());
+    let pinned_host = res.text().await?;
+
+    // Round robin would alternate, but the cookie keeps the client on the
+    // same target.
+    for _ in 0..4 {
+        let res = client
+            .get(&url)
+            .header("cookie", cookie.as_str())
+            .send()
+            .await?;
+
+        assert!(res.headers().get(header::SET_COOKIE.as_str()).is_none());
+        assert_eq!(res.text().await?, pinned_host);
+    }
+
+    let res = client
+        .get(&url)
+        .header("cookie", "affinity=forged.value")
+        .send()
+        .await?;
+    assert!(res.headers().get(header::SET_COOKIE.as_str()).is_some());
+
+    // A client pinned to a target that's down is moved to another one.
+    let down = joubini::affinity::sign(
+        proxy_config.affinity.as_ref().unwrap(),
+        &proxy_config,
+        "http://127.0.0.1:3058",
+    );
+
+    let res = client
+        .get(&url)
+        .header("cookie", format!("affinity={}", down))
+        .send()
+        .await?;
+
+    let cookie = res.headers()[header::SET_COOKIE.as_str()].to_str()?;
+    assert!(!cookie.contains(&down));
+    assert_ne!(res.text().await?, "127.0.0.1:3058");
+
+    server.shutdown();
+    server.join().await?;
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_websocket_upgrade() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("ws:3017")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_websocket_remote(3017).await;
+
+    let server = ProxyServer::builder().settings(settings).bind().await?;
+    let balancer = server.balancer();
+    tokio::spawn(server.join());
+
+    let (mut ws, res) =
+        tokio_tungstenite::connect_async("ws://localhost:7878/ws").await?;
+
+    assert_eq!(res.status(), hyper::StatusCode::SWITCHING_PROTOCOLS);
+
+    ws.send(Message::Text(String::from("ping"))).await?;
+
+    let msg = ws.next().await.expect("Web socket closed unexpectedly")?;
+    assert_eq!(msg, Message::Text(String::from("ping")));
+
+    // An open tunnel counts towards the upstream's load.
+    let upstream = "http://localhost:3017";
+    assert_eq!(balancer.active_requests(upstream), 1);
+
+    ws.close(None).await?;
+    drop(ws);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(balancer.active_requests(upstream), 0);
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_websocket_extended_connect() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("ws:3018")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_websocket_remote(3018).await;
+    start_joubini(settings).await;
+
+    let stream = tokio::net::TcpStream::connect("localhost:7878").await?;
+
+    let (mut sender, connection) =
+        hyper::client::conn::http2::Builder::new(TokioExecutor::new())
+            .handshake(TokioIo::new(stream))
+            .await?;
+
+    tokio::spawn(connection);
+
+    let mut req = hyper::Request::builder()
+        .method(hyper::Method::CONNECT)
+        .uri("http://localhost:7878/ws")
+        .header(hyper::header::SEC_WEBSOCKET_VERSION, "13")
+        .body(Empty::<Bytes>::new())?;
+
+    req.extensions_mut()
+        .insert(hyper::ext::Protocol::from_static("websocket"));
+
+    sender.ready().await?;
+    let mut res = sender.send_request(req).await?;
+
+    assert_eq!(res.status(), hyper::StatusCode::OK);
+
+    let upgraded = hyper::upgrade::on(&mut res).await?;
+
+    let mut ws = WebSocketStream::from_raw_socket(
+        TokioIo::new(upgraded),
+        Role::Client,
+        None,
+    )
+    .await;
+
+    ws.send(Message::Text(String::from("ping"))).await?;
+
+    let msg = ws.next().await.expect("Web socket closed unexpectedly")?;
+    assert_eq!(msg, Message::Text(String::from("ping")));
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_reuse_upstream_connections() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str(":3019")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_remote(3019, "/").await;
+    start_joubini(settings).await;
+
+    let client = reqwest::Client::new();
+
+    let first_peer = client
+        .get("http://localhost:7878/peer")
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    let second_peer = client
+        .get("http://localhost:7878/peer")
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    assert_eq!(first_peer, second_peer);
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_proxy_to_remote_host() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str("remote:127.0.0.1:3020/host")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_remote(3020, "/host").await;
+    start_joubini(settings).await;
+
+    let res = reqwest::get("http://localhost:7878/remote").await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_https_upstream() -> Result<(), Box<dyn Error>> {
+    let upstream_settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7879,
+        proxies: vec![ProxyConfig::from_str(":3021").unwrap()],
+        config: None,
+        tls: true,
+        pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
+        key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+        ..Settings::default()
+    };
+
+    let mut proxy = ProxyConfig::from_str(":https://localhost:7879")?;
+    proxy.tls.ca = Some(PathBuf::from_str("/tmp/localCA.pem").unwrap());
+
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![proxy],
+        config: None,
+        tls: false,
+        pem: None,
+        key: None,
+        ..Settings::default()
+    };
+
+    start_remote(3021, "/").await;
+    start_joubini(upstream_settings).await;
+    start_joubini(settings).await;
+
+    let res = reqwest::get("http://localhost:7878").await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "get_ok");
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_insecure_https_upstream() -> Result<(), Box<dyn Error>> {
+    let upstream_settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7879,
+        proxies: vec![ProxyConfig::from_str(":3022").unwrap()],
+        config: None,
+        tls: true,
+        pem: Some(PathBuf::from_str("/tmp/localhost.crt").unwrap()),
+        key: Some(PathBuf::from_str("/tmp/localhost.key").unwrap()),
+        ..Settings::default()
+    };
+
+    let verified_proxy =
+        ProxyConfig::from_str("verified:https://localhost:7879")?;
+
+    let mut insecure_proxy =
+        ProxyConfig::from_str("insecure:https://localhost:7879")?;
+    insecure_proxy.tls.insecure = true;
+
+    let settings = Settings {
+        host: String::from("localhost"),
+        local_port: 7878,
+        proxies: vec![verified_proxy, insecure_proxy],
+        config: None,
+        tls: false,
+        pem: None,
+        key: None,
+        ..Settings::default()
+    };
+
+    start_remote(3022, "/").await;
+    start_joubini(upstream_settings).await;
+    start_joubini(settings).await;
+
+    let insecure = reqwest::get("http://localhost:7878/insecure").await?;
+    assert_eq!(insecure.status(), StatusCode::OK);
+    assert_eq!(insecure.text().await?, "get_ok");
+
+    let verified = reqwest::get("http://localhost:7878/verified").await?;
+    assert_eq!(verified.status(), StatusCode::BAD_GATEWAY);
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_h2c_upstream() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str(":h2c://localhost:3023")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_h2c_remote(3023).await;
+    start_joubini(settings).await;
+
+    let res = reqwest::get("http://localhost:7878").await?;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "HTTP/2.0");
+
+    Ok(())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_h2c_upstream_preserves_trailers() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        config: None,
+        host: String::from("localhost"),
+        local_port: 7878,
+        tls: false,
+        pem: None,
+        key: None,
+        proxies: vec![ProxyConfig::from_str(":h2c://localhost:3024")
+            .expect("Unable to parse proxy string")],
+        ..Settings::default()
+    };
+
+    start_h2c_remote(3024).await;
+    start_joubini(settings).await;
+
+    let stream = tokio::net::TcpStream::connect("localhost:7878").await?;
+
+    let (mut sender, connection) =
+        hyper::client::conn::http2::Builder::new(TokioExecutor::new())
+            .handshake(TokioIo::new(stream))
+            .await?;
+
+    tokio::spawn(connection);
+
+    let req = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri("http://localhost:7878/grpc.Service/Method")
+        .header(hyper::header::TE, "trailers")
+        .header(hyper::header::CONTENT_TYPE, "application/grpc")
+        .body(Empty::<Bytes>::new())?;
+
+    let res = sender.send_request(req).await?;
+
+    assert_eq!(res.status(), hyper::StatusCode::OK);
+    assert_eq!(
+        res.headers().get("x-upstream-te").unwrap(),
+        hyper::header::HeaderValue::from_static("trailers")
+    );
+
+    let body = res.into_body().collect().await?;
+
+    assert_eq!(
+        body.trailers().unwrap().get("grpc-status").unwrap(),
+        hyper::header::HeaderValue::from_static("0")
+    );
+    assert_eq!(body.to_bytes(), Bytes::from_static(b"HTTP/2.0"));
+
+    Ok(())
+}
+
+// TODO: http2
+// let client = reqwest::Client::builder()
+//     .use_native_tls()
+//     .identity(cert)
+//     .add_root_certificate(ca)
+//     .pool_max_idle_per_host(0)
+// .http2_prior_knowledge()
+//     .build()?;
+
+async fn start_joubini(settings: Settings) {
+    let server = ProxyServer::builder()
+        .settings(settings)
+        .bind()
+        .await
+        .expect("Unable to start server");
+
+    tokio::spawn(server.join());
+}
+
+async fn start_h2c_remote(port: u16) {
+    let listener = tokio::net::TcpListener::bind(format!("localhost:{}", port))
+        .await
+        .expect("Unable to listen on port");
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener
+                .accept()
+                .await
+                .expect("Unable to accept connection");
+
+            tokio::spawn(async move {
+                let _ = hyper::server::conn::http2::Builder::new(
+                    TokioExecutor::new(),
+                )
+                .serve_connection(TokioIo::new(stream), service_fn(h2c_ok))
+                .await;
+            });
+        }
+    });
+}
+
+async fn h2c_ok(
+    req: hyper::Request<Incoming>,
+) -> Result<hyper::Response<BoxBody<Bytes, Infallible>>, Infallible> {
+    let mut trailers = hyper::HeaderMap::new();
+    trailers
+        .insert("grpc-status", hyper::header::HeaderValue::from_static("0"));
+
+    let frames = futures_util::stream::iter(vec![
+        Ok(Frame::data(Bytes::from(format!("{:?}", req.version())))),
+        Ok(Frame::trailers(trailers)),
+    ]);
+
+    let mut res = hyper::Response::new(BodyExt::boxed(StreamBody::new(frames)));
+
+    if let Some(te) = req.headers().get(hyper::header::TE) {
+        res.headers_mut().insert("x-upstream-te", te.clone());
+    }
+
+    Ok(res)
+}
+
+async fn start_websocket_remote(port: u16) {
+    let listener = tokio::net::TcpListener::bind(format!("localhost:{}", port))
+        .await
+        .expect("Unable to listen on port");
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener
+                .accept()
+                .await
+                .expect("Unable to accept connection");
+
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream)
+                    .await
+                    .expect("Unable to accept web socket");
+
+                while let Some(Ok(msg)) = ws.next().await {
+                    if msg.is_text() {
+                        ws.send(msg).await.expect("Unable to echo message");
+                    }
+                }
+            });
+        }
+    });
+}
+
+async fn start_remote(port: u16, path: &'static str) {
+    let listener = TcpListener::bind(format!("localhost:{}", port))
+        .expect("Unable to listen on port");
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .route(path, web::get().to(get_ok))
+            .route("/json-post", web::post().to(post_json_ok))
+            .route("/form-post", web::post().to(post_form_ok))
+            .route("/add-forwarded", web::get().to(add_forwarded_ok))
+            .route("/append-forwarded", web::get().to(append_forwarded_ok))
+            .route("/301", web::get().to(handler_301))
+            .route("/404", web::get().to(handler_404))
+            .route("/500", web::get().to(handler_500))
+            .route("/headers", web::get().to(headers_ok))
+            .route("/host", web::get().to(host_ok))
+            .route("/peer", web::get().to(peer_ok))
+            .route("/echo-uri/{tail:.*}", web::get().to(echo_uri_ok))
+            .route("/slow/{millis}", web::get().to(slow_ok))
+    })
+    .listen(listener)
+    .expect("Unable to start remote server")
+    .run();
+
+    tokio::spawn(server);
+}
+
+async fn handler_301() -> HttpResponse {
+    HttpResponse::MovedPermanently().finish()
+}
+
+async fn handler_404() -> HttpResponse {
+    HttpResponse::NotFound().finish()
+}
+
+async fn handler_500() -> HttpResponse {
+    HttpResponse::InternalServerError().finish()
+}
+
+async fn echo_uri_ok(req: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok().body(req.uri().to_string())
+}
+
+async fn slow_ok(millis: web::Path<u64>) -> HttpResponse {
+    tokio::time::sleep(Duration::from_millis(*millis)).await;
+
+    HttpResponse::Ok().body("slow_ok")
+}
+
+async fn get_ok() -> HttpResponse {
+    HttpResponse::Ok().body("get_ok")
+}
+
+async fn host_ok(req: HttpRequest) -> HttpResponse {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+
+    HttpResponse::Ok().body(String::from(host))
 }
 
 async fn headers_ok(req: HttpRequest) -> HttpResponse {
@@ -734,6 +2878,13 @@ async fn headers_ok(req: HttpRequest) -> HttpResponse {
     HttpResponse::Ok().finish()
 }
 
+async fn peer_ok(req: HttpRequest) -> HttpResponse {
+    match req.peer_addr() {
+        Some(peer) => HttpResponse::Ok().body(peer.to_string()),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 async fn post_json_ok(body: web::Json<PostData>) -> HttpResponse {
     if body.data == "post_data" {
         let json_ok = ResponseData {
@@ -2,7 +2,12 @@ use std::{error::Error, ffi::OsString, path::PathBuf, str::FromStr};
 
 use joubini::{
     cli::Cli,
-    settings::{get_settings, ProxyConfig, Settings},
+    settings::{
+        get_settings, AccessLogConfig, AccessLogFormat, AdminConfig,
+        AffinityConfig, CertificateConfig, HashKey, HealthCheckConfig,
+        ListenerConfig, LoadBalancing, ProxyConfig, RouteEdit, Scheme,
+        Settings, UpstreamProtocol, UpstreamTarget, UpstreamTls,
+    },
 };
 
 #[test]
@@ -16,6 +21,7 @@ fn test_parse_proxy_config_from_str() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/"),
             remote_port: 3000,
             remote_path: String::from("/"),
+            ..ProxyConfig::default()
         },
     );
 
@@ -28,6 +34,7 @@ fn test_parse_proxy_config_from_str() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/"),
             remote_port: 3000,
             remote_path: String::from("/api"),
+            ..ProxyConfig::default()
         }
     );
 
@@ -40,6 +47,7 @@ fn test_parse_proxy_config_from_str() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/api"),
             remote_port: 3000,
             remote_path: String::from("/"),
+            ..ProxyConfig::default()
         }
     );
 
@@ -52,6 +60,7 @@ fn test_parse_proxy_config_from_str() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/api"),
             remote_port: 3000,
             remote_path: String::from("/api"),
+            ..ProxyConfig::default()
         }
     );
 
@@ -64,12 +73,503 @@ fn test_parse_proxy_config_from_str() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/local/v1"),
             remote_port: 3000,
             remote_path: String::from("/api/v1"),
+            ..ProxyConfig::default()
         }
     );
 
     Ok(())
 }
 
+#[test]
+fn test_parse_proxy_config_with_remote_host_from_str(
+) -> Result<(), Box<dyn Error>> {
+    let p1 = "api:backend.local:3001/api"; // local_path:remote_host:remote_port/remote_path
+    let o1 = ProxyConfig::from_str(p1);
+
+    assert_eq!(
+        o1.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/api"),
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 3001,
+            remote_path: String::from("/api"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p2 = ":192.168.0.10:3000"; // :remote_host:remote_port
+    let o2 = ProxyConfig::from_str(p2);
+
+    assert_eq!(
+        o2.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/"),
+            remote_host: Some(String::from("192.168.0.10")),
+            remote_port: 3000,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p3 = "api:http://backend.local:3001/api"; // local_path:url
+    let o3 = ProxyConfig::from_str(p3);
+
+    assert_eq!(
+        o3.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/api"),
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 3001,
+            remote_path: String::from("/api"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p4 = "api:http://backend.local"; // local_path:url without port or path
+    let o4 = ProxyConfig::from_str(p4);
+
+    assert_eq!(
+        o4.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/api"),
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 80,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    assert!(ProxyConfig::from_str("api::3000").is_err());
+    assert!(ProxyConfig::from_str("api:ftp://backend.local:21").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_proxy_config_with_scheme_from_str() -> Result<(), Box<dyn Error>>
+{
+    let p1 = "api:https://backend.local:3001/api";
+    let o1 = ProxyConfig::from_str(p1);
+
+    assert_eq!(
+        o1.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/api"),
+            scheme: Scheme::Https,
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 3001,
+            remote_path: String::from("/api"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p3 = "grpc:h2c://backend.local:50051";
+    let o3 = ProxyConfig::from_str(p3);
+
+    assert_eq!(
+        o3.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/grpc"),
+            scheme: Scheme::Http,
+            protocol: UpstreamProtocol::H2c,
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 50051,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p4 = "grpc:h2://backend.local:50051";
+    let o4 = ProxyConfig::from_str(p4);
+
+    assert_eq!(
+        o4.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/grpc"),
+            scheme: Scheme::Https,
+            protocol: UpstreamProtocol::H2,
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 50051,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    let p2 = ":https://backend.local";
+    let o2 = ProxyConfig::from_str(p2);
+
+    assert_eq!(
+        o2.unwrap(),
+        ProxyConfig {
+            local_path: String::from("/"),
+            scheme: Scheme::Https,
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 443,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_proxy_config_with_local_host_from_str(
+) -> Result<(), Box<dyn Error>> {
+    assert_eq!(
+        ProxyConfig::from_str("//app.localhost:3000")?,
+        ProxyConfig {
+            local_host: Some(String::from("app.localhost")),
+            local_path: String::from("/"),
+            remote_port: 3000,
+            remote_path: String::from("/"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    assert_eq!(
+        ProxyConfig::from_str("//*.Localhost/api:backend.local:3001/v1")?,
+        ProxyConfig {
+            local_host: Some(String::from("*.localhost")),
+            local_path: String::from("/api"),
+            remote_host: Some(String::from("backend.local")),
+            remote_port: 3001,
+            remote_path: String::from("/v1"),
+            ..ProxyConfig::default()
+        }
+    );
+
+    assert_eq!(
+        ProxyConfig::from_str("//app.localhost/api:3000")?.to_string(),
+        "app.localhost/api -> :3000/"
+    );
+
+    assert!(ProxyConfig::from_str("//:3000").is_err());
+    assert!(ProxyConfig::from_str("///api:3000").is_err());
+    assert!(ProxyConfig::from_str("//app.*.localhost:3000").is_err());
+    assert!(ProxyConfig::from_str("//*:3000").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_local_hosts_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-vhosts.yml"))?;
+
+    assert_eq!(
+        settings.proxies,
+        vec![
+            ProxyConfig {
+                local_host: Some(String::from("app.localhost")),
+                local_path: String::from("/"),
+                remote_port: 3000,
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            },
+            ProxyConfig {
+                local_host: Some(String::from("*.localhost")),
+                local_path: String::from("/api"),
+                remote_port: 3001,
+                remote_path: String::from("/api"),
+                ..ProxyConfig::default()
+            },
+            ProxyConfig {
+                local_host: Some(String::from("admin.localhost")),
+                local_path: String::from("/"),
+                remote_port: 3002,
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_listeners_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-listeners.yml"))?;
+
+    assert_eq!(
+        settings.listeners,
+        vec![
+            ListenerConfig {
+                port: 8080,
+                redirect_to_https: Some(8443),
+                ..ListenerConfig::default()
+            },
+            ListenerConfig {
+                port: 8443,
+                tls: true,
+                pem: Some(PathBuf::from("/tmp/localhost.crt")),
+                key: Some(PathBuf::from("/tmp/localhost.key")),
+                proxies: Some(vec![ProxyConfig {
+                    local_path: String::from("/api"),
+                    remote_port: 3001,
+                    remote_path: String::from("/"),
+                    ..ProxyConfig::default()
+                }]),
+                ..ListenerConfig::default()
+            },
+        ]
+    );
+
+    let listener_settings = settings.listener_settings();
+
+    assert_eq!(listener_settings.len(), 2);
+
+    assert_eq!(listener_settings[0].host, "localhost");
+    assert_eq!(listener_settings[0].local_port, 8080);
+    assert!(!listener_settings[0].tls);
+    assert_eq!(listener_settings[0].redirect_to_https, Some(8443));
+    assert_eq!(listener_settings[0].proxies, settings.proxies);
+
+    assert_eq!(listener_settings[1].local_port, 8443);
+    assert!(listener_settings[1].tls);
+    assert_eq!(listener_settings[1].redirect_to_https, None);
+    assert_eq!(listener_settings[1].proxies[0].local_path, "/api");
+
+    Ok(())
+}
+
+#[test]
+fn test_listener_settings_without_listeners() -> Result<(), Box<dyn Error>> {
+    let settings = Settings {
+        local_port: 7878,
+        proxies: vec![ProxyConfig::from_str(":3000")?],
+        ..Settings::default()
+    };
+
+    assert_eq!(settings.listener_settings(), vec![settings.clone()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_certificates_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-certificates.yml"))?;
+
+    assert_eq!(
+        settings.certificates,
+        vec![
+            CertificateConfig {
+                hosts: vec![
+                    String::from("app.localhost"),
+                    String::from("*.app.localhost")
+                ],
+                pem: PathBuf::from("/tmp/app.localhost.crt"),
+                key: PathBuf::from("/tmp/app.localhost.key"),
+            },
+            CertificateConfig {
+                hosts: vec![String::from("api.localhost")],
+                pem: PathBuf::from("/tmp/api.localhost.crt"),
+                key: PathBuf::from("/tmp/api.localhost.key"),
+            },
+        ]
+    );
+
+    assert_eq!(settings.pem, Some(PathBuf::from("/tmp/localhost.crt")));
+    assert_eq!(settings.key, Some(PathBuf::from("/tmp/localhost.key")));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_access_log_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-access-log.yml"))?;
+
+    assert_eq!(
+        settings.access_log,
+        AccessLogConfig {
+            format: AccessLogFormat::Json,
+            path: Some(PathBuf::from("/tmp/joubini-access.log")),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_admin_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings = Settings::try_from(PathBuf::from("tests/config-admin.yml"))?;
+
+    assert_eq!(
+        settings.admin,
+        Some(AdminConfig {
+            host: Some(String::from("127.0.0.1")),
+            port: 9090,
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_upstream_targets_from_config_file() -> Result<(), Box<dyn Error>>
+{
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-targets.yml"))?;
+
+    assert_eq!(
+        settings.proxies,
+        vec![
+            ProxyConfig {
+                local_path: String::from("/api"),
+                remote_port: 3000,
+                remote_path: String::from("/api"),
+                targets: vec![
+                    UpstreamTarget {
+                        host: None,
+                        port: 3001,
+                    },
+                    UpstreamTarget {
+                        host: Some(String::from("backend.local")),
+                        port: 3002,
+                    },
+                ],
+                health_check: Some(HealthCheckConfig {
+                    path: String::from("/health"),
+                    interval: 5,
+                    timeout: 2,
+                    healthy_threshold: 2,
+                    unhealthy_threshold: 2,
+                }),
+                load_balancing: LoadBalancing::RoundRobin,
+                affinity: Some(AffinityConfig {
+                    cookie: String::from("api_affinity"),
+                    secret: Some(String::from("s3cret")),
+                }),
+                ..ProxyConfig::default()
+            },
+            ProxyConfig {
+                local_path: String::from("/"),
+                remote_port: 4000,
+                remote_path: String::from("/"),
+                targets: vec![UpstreamTarget {
+                    host: None,
+                    port: 4001,
+                }],
+                load_balancing: LoadBalancing::Hash(HashKey::Header(
+                    String::from("x-user-id")
+                )),
+                affinity: Some(AffinityConfig {
+                    cookie: String::from("joubini_affinity"),
+                    secret: None,
+                }),
+                ..ProxyConfig::default()
+            },
+        ]
+    );
+
+    let targets = settings.proxies[0].upstream_targets();
+
+    assert_eq!(targets.len(), 3);
+    assert_eq!(targets[2].remote_host, Some(String::from("backend.local")));
+    assert_eq!(targets[2].remote_port, 3002);
+    assert_eq!(targets[2].remote_path, String::from("/api"));
+    assert!(targets.iter().all(|t| t.targets.is_empty()));
+
+    assert!(UpstreamTarget::from_str("backend.local:port").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_load_balancing_from_str() -> Result<(), Box<dyn Error>> {
+    let strategies = [
+        ("failover", LoadBalancing::Failover),
+        ("round_robin", LoadBalancing::RoundRobin),
+        ("least_connections", LoadBalancing::LeastConnections),
+        ("random", LoadBalancing::Random),
+        (
+            "hash:header:x-user-id",
+            LoadBalancing::Hash(HashKey::Header(String::from("x-user-id"))),
+        ),
+        (
+            "hash:cookie:session",
+            LoadBalancing::Hash(HashKey::Cookie(String::from("session"))),
+        ),
+        ("hash:client_ip", LoadBalancing::Hash(HashKey::ClientIp)),
+    ];
+
+    for (s, strategy) in strategies {
+        assert_eq!(LoadBalancing::from_str(s)?, strategy);
+        assert_eq!(strategy.to_string(), s);
+    }
+
+    for s in ["", "weighted", "hash", "hash:header:", "hash:query:id"] {
+        assert!(LoadBalancing::from_str(s).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fail_invalid_access_log_format() -> Result<(), Box<dyn Error>> {
+    let cli_args = vec![
+        OsString::from("empty first value to discard"),
+        OsString::from("--access-log-format=apache"),
+    ];
+
+    assert!(get_settings(cli_args).is_err());
+    assert!(AccessLogFormat::from_str("apache").is_err());
+    assert_eq!(AccessLogFormat::from_str("json")?, AccessLogFormat::Json);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_upstream_tls_from_config_file() -> Result<(), Box<dyn Error>> {
+    let settings =
+        Settings::try_from(PathBuf::from("tests/config-upstream-tls.yml"))?;
+
+    assert_eq!(
+        settings.proxies,
+        vec![
+            ProxyConfig {
+                local_path: String::from("/"),
+                remote_port: 3000,
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            },
+            ProxyConfig {
+                local_path: String::from("/api"),
+                scheme: Scheme::Https,
+                remote_host: Some(String::from("backend.local")),
+                remote_port: 3001,
+                remote_path: String::from("/api"),
+                protocol: UpstreamProtocol::H2,
+                tls: UpstreamTls {
+                    ca: Some(PathBuf::from("/tmp/localCA.pem")),
+                    sni: Some(String::from("api.backend.local")),
+                    insecure: false,
+                },
+                ..ProxyConfig::default()
+            },
+            ProxyConfig {
+                local_path: String::from("/admin"),
+                scheme: Scheme::Https,
+                remote_host: Some(String::from("admin.local")),
+                remote_port: 443,
+                remote_path: String::from("/"),
+                protocol: UpstreamProtocol::Http1,
+                tls: UpstreamTls {
+                    ca: None,
+                    sni: None,
+                    insecure: true,
+                },
+                ..ProxyConfig::default()
+            },
+        ]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_parse_settings_from_config_file_with_optional_fields(
 ) -> Result<(), Box<dyn Error>> {
@@ -91,28 +591,53 @@ fn test_parse_settings_from_config_file_with_optional_fields(
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/local/v1"),
                     remote_port: 3000,
                     remote_path: String::from("/remote/v1"),
+                    ..ProxyConfig::default()
                 }
-            ]
+            ],
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout: 30,
+            connect_timeout: 5,
+            response_timeout: 30,
+            shutdown_timeout: 10,
+            ejection_time: 5,
+            fallback: Some(ProxyConfig {
+                local_path: String::from("/"),
+                remote_port: 4000,
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            }),
+            dev: true,
+            listeners: vec![],
+            redirect_to_https: None,
+            ca_dir: None,
+            certificates: vec![],
+            access_log: AccessLogConfig::default(),
+            admin: None,
+            route_edits: vec![],
         }
     );
 
@@ -141,28 +666,34 @@ fn test_parse_settings_from_config_file_without_optional_fields(
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/local/v1"),
                     remote_port: 3000,
                     remote_path: String::from("/remote/v1"),
+                    ..ProxyConfig::default()
                 }
-            ]
+            ],
+            ..Settings::default()
         }
     );
 
@@ -178,6 +709,14 @@ fn test_parse_settings_from_cli() -> Result<(), Box<dyn Error>> {
         tls: true,
         pem: Some(PathBuf::from("/tmp/localhost.crt")),
         key: Some(PathBuf::from("/tmp/localhost.key")),
+        fallback: None,
+        dev: false,
+        ca_dir: None,
+        access_log: Some(PathBuf::from("/tmp/joubini-access.log")),
+        access_log_format: Some(String::from("combined")),
+        admin_port: Some(9090),
+        log_level: None,
+        command: None,
         proxies: vec![
             String::from(":3000"),
             String::from(":3000/api"),
@@ -203,28 +742,42 @@ fn test_parse_settings_from_cli() -> Result<(), Box<dyn Error>> {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/local/v1"),
                     remote_port: 3000,
                     remote_path: String::from("/remote/v1"),
+                    ..ProxyConfig::default()
                 }
-            ]
+            ],
+            access_log: AccessLogConfig {
+                format: AccessLogFormat::Combined,
+                path: Some(PathBuf::from("/tmp/joubini-access.log")),
+            },
+            admin: Some(AdminConfig {
+                host: None,
+                port: 9090,
+            }),
+            ..Settings::default()
         }
     );
 
@@ -244,7 +797,9 @@ fn test_merge_settings_structs() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/local_one"),
             remote_port: 3001,
             remote_path: String::from("/remote_one"),
+            ..ProxyConfig::default()
         }],
+        ..Settings::default()
     };
 
     let mut settings_2 = Settings {
@@ -258,7 +813,9 @@ fn test_merge_settings_structs() -> Result<(), Box<dyn Error>> {
             local_path: String::from("/local_two"),
             remote_port: 3002,
             remote_path: String::from("/remote_two"),
+            ..ProxyConfig::default()
         }],
+        ..Settings::default()
     };
 
     let merged_settings = settings_1.merge(&mut settings_2);
@@ -277,13 +834,16 @@ fn test_merge_settings_structs() -> Result<(), Box<dyn Error>> {
                     local_path: String::from("/local_one"),
                     remote_port: 3001,
                     remote_path: String::from("/remote_one"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/local_two"),
                     remote_port: 3002,
                     remote_path: String::from("/remote_two"),
+                    ..ProxyConfig::default()
                 },
-            ]
+            ],
+            ..Settings::default()
         }
     );
 
@@ -303,6 +863,21 @@ fn test_create_new_settings() -> Result<(), Box<dyn Error>> {
             tls: false,
             pem: None,
             key: None,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: 90,
+            connect_timeout: 10,
+            response_timeout: 60,
+            shutdown_timeout: 30,
+            ejection_time: 10,
+            fallback: None,
+            dev: false,
+            listeners: vec![],
+            redirect_to_https: None,
+            ca_dir: None,
+            certificates: vec![],
+            access_log: AccessLogConfig::default(),
+            admin: None,
+            route_edits: vec![],
         }
     );
 
@@ -322,6 +897,21 @@ fn test_create_default_settings() -> Result<(), Box<dyn Error>> {
             tls: false,
             pem: None,
             key: None,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: 90,
+            connect_timeout: 10,
+            response_timeout: 60,
+            shutdown_timeout: 30,
+            ejection_time: 10,
+            fallback: None,
+            dev: false,
+            listeners: vec![],
+            redirect_to_https: None,
+            ca_dir: None,
+            certificates: vec![],
+            access_log: AccessLogConfig::default(),
+            admin: None,
+            route_edits: vec![],
         }
     );
 
@@ -351,8 +941,33 @@ fn test_print_settings() -> Result<(), Box<dyn Error>> {
         .proxies
         .push(ProxyConfig::from_str("baz:3001/qux").unwrap());
 
+    settings
+        .proxies
+        .push(ProxyConfig::from_str("fred:backend.local:3002/thud").unwrap());
+
     assert_eq!(settings.to_string(),
-                    String::from("\n\x1b[95mᴥ\x1b[0m 127.0.0.1:80/foo \x1b[94m➡\x1b[0m :3000/bar\n\x1b[95mᴥ\x1b[0m 127.0.0.1:80/baz \x1b[94m➡\x1b[0m :3001/qux\n"));
+                    String::from("\nᴥ 127.0.0.1:80/foo ➡ :3000/bar\nᴥ 127.0.0.1:80/baz ➡ :3001/qux\nᴥ 127.0.0.1:80/fred ➡ backend.local:3002/thud\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_print_settings_with_upstream_targets() -> Result<(), Box<dyn Error>> {
+    let mut settings = Settings::default();
+
+    settings.proxies.push(ProxyConfig {
+        targets: vec![
+            UpstreamTarget::from_str(":3001")?,
+            UpstreamTarget::from_str("backend.local:3002")?,
+        ],
+        load_balancing: LoadBalancing::LeastConnections,
+        ..ProxyConfig::from_str("api:3000/v1")?
+    });
+
+    assert_eq!(
+        settings.to_string(),
+        String::from("\nᴥ 127.0.0.1:80/api ➡ :3000/v1, :3001/v1, backend.local:3002/v1 (least_connections)\n")
+    );
 
     Ok(())
 }
@@ -370,6 +985,25 @@ fn test_invalid_proxy_config() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn test_fail_invalid_remote_host() -> Result<(), Box<dyn Error>> {
+    for p in [
+        "api:bad host:3000",
+        "api:bad<host:3000",
+        "//app.localhost/api:bad host:3000",
+    ] {
+        assert_eq!(
+            ProxyConfig::from_str(p).unwrap_err().to_string(),
+            String::from("Parse error: Unable to parse proxy definition.")
+        );
+    }
+
+    assert!(UpstreamTarget::from_str("bad host:3001").is_err());
+    assert!(UpstreamTarget::from_str("backend.local:3001").is_ok());
+
+    Ok(())
+}
+
 #[test]
 fn test_fail_invalid_port() -> Result<(), Box<dyn Error>> {
     let p1 = ":foo";
@@ -423,6 +1057,8 @@ fn test_get_settings_without_config_file() -> Result<(), Box<dyn Error>> {
         OsString::from("--tls"),
         OsString::from("--pem=foo/bar.pem"),
         OsString::from("--key=bar/baz.key"),
+        OsString::from("--fallback=:4000/fallback"),
+        OsString::from("--dev"),
     ];
 
     let settings = get_settings(cli_args)
@@ -440,14 +1076,40 @@ fn test_get_settings_without_config_file() -> Result<(), Box<dyn Error>> {
             proxies: vec![ProxyConfig {
                 local_path: String::from("/"),
                 remote_port: 3000,
-                remote_path: String::from("/")
-            }]
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            }],
+            fallback: Some(ProxyConfig {
+                local_path: String::from("/"),
+                remote_port: 4000,
+                remote_path: String::from("/fallback"),
+                ..ProxyConfig::default()
+            }),
+            dev: true,
+            ..Settings::default()
         }
     );
 
     Ok(())
 }
 
+#[test]
+fn test_fail_fallback_with_local_path() -> Result<(), Box<dyn Error>> {
+    let cli_args = vec![
+        OsString::from("empty first value to discard"),
+        OsString::from("--fallback=app:3000"),
+    ];
+
+    let err = get_settings(cli_args).unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        String::from("Parse error: Fallback proxy can't have a local path '/app', as it handles requests to any path")
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_settings_with_config_file() -> Result<(), Box<dyn Error>> {
     let cli_args = vec![
@@ -472,30 +1134,102 @@ fn test_settings_with_config_file() -> Result<(), Box<dyn Error>> {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/api"),
                     remote_port: 3000,
                     remote_path: String::from("/api"),
+                    ..ProxyConfig::default()
                 },
                 ProxyConfig {
                     local_path: String::from("/local/v1"),
                     remote_port: 3000,
                     remote_path: String::from("/remote/v1"),
+                    ..ProxyConfig::default()
                 }
-            ]
+            ],
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout: 30,
+            connect_timeout: 5,
+            response_timeout: 30,
+            shutdown_timeout: 10,
+            ejection_time: 5,
+            fallback: Some(ProxyConfig {
+                local_path: String::from("/"),
+                remote_port: 4000,
+                remote_path: String::from("/"),
+                ..ProxyConfig::default()
+            }),
+            dev: true,
+            listeners: vec![],
+            redirect_to_https: None,
+            ca_dir: None,
+            certificates: vec![],
+            access_log: AccessLogConfig::default(),
+            admin: None,
+            route_edits: vec![],
         }
     );
 
     Ok(())
 }
+
+#[test]
+fn test_replay_route_edits() -> Result<(), Box<dyn Error>> {
+    let mut edited = Settings {
+        proxies: vec![
+            ProxyConfig::from_str("api:3000")?,
+            ProxyConfig::from_str("admin:3001")?,
+        ],
+        ..Settings::default()
+    };
+
+    assert!(
+        edited.edit_route(RouteEdit::Set(ProxyConfig::from_str("new:3002")?))
+    );
+    assert!(
+        edited.edit_route(RouteEdit::Set(ProxyConfig::from_str("new:3003")?))
+    );
+    assert!(
+        edited.edit_route(RouteEdit::Remove(ProxyConfig::from_str("admin:0")?))
+    );
+    assert!(!edited
+        .edit_route(RouteEdit::Remove(ProxyConfig::from_str("missing:0")?)));
+
+    // Only the latest change to each route is kept.
+    assert_eq!(edited.route_edits.len(), 2);
+
+    let mut reloaded = Settings {
+        proxies: vec![
+            ProxyConfig::from_str("api:4000")?,
+            ProxyConfig::from_str("admin:4001")?,
+        ],
+        ..Settings::default()
+    };
+
+    reloaded.replay_route_edits(&edited.route_edits);
+
+    assert_eq!(
+        reloaded.proxies,
+        vec![
+            ProxyConfig::from_str("api:4000")?,
+            ProxyConfig::from_str("new:3003")?,
+        ]
+    );
+    assert_eq!(reloaded.route_edits, edited.route_edits);
+
+    Ok(())
+}